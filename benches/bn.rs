@@ -1,19 +1,28 @@
-use criterion::{Criterion, black_box, criterion_group, criterion_main};
+use criterion::{Criterion, criterion_group, criterion_main};
 use mateus::benchmark::BRATKO_KOPEC;
 use mateus::mgen::*;
 use mateus::val::Colour;
+use std::hint::black_box;
 
 fn bench_move_gen(c: &mut Criterion) {
     let fen = BRATKO_KOPEC[0].0;
-    let board = Board::from_fen(fen);
+    let board = Board::from_fen(fen).unwrap();
     c.bench_function("mgen BK1", |b| {
         b.iter(|| black_box(board.moves(false, false)))
     });
 }
 
+fn bench_legal_move_gen(c: &mut Criterion) {
+    let fen = BRATKO_KOPEC[0].0;
+    let board = Board::from_fen(fen).unwrap();
+    c.bench_function("legal mgen BK1", |b| {
+        b.iter(|| black_box(board.legal_moves(false)))
+    });
+}
+
 fn bench_in_check(c: &mut Criterion) {
     let fen = BRATKO_KOPEC[0].0;
-    let board = Board::from_fen(fen);
+    let board = Board::from_fen(fen).unwrap();
     c.bench_function("in_check BK1", |b| {
         b.iter(|| black_box(board.in_check(Colour::white())))
     });
@@ -21,21 +30,28 @@ fn bench_in_check(c: &mut Criterion) {
 
 fn bench_mobility(c: &mut Criterion) {
     let fen = BRATKO_KOPEC[0].0;
-    let board = Board::from_fen(fen);
+    let board = Board::from_fen(fen).unwrap();
     c.bench_function("mobility BK1", |b| b.iter(|| black_box(board.mobility())));
 }
 
 fn bench_pawn_structure(c: &mut Criterion) {
     let fen = BRATKO_KOPEC[0].0;
-    let board = Board::from_fen(fen);
+    let board = Board::from_fen(fen).unwrap();
     c.bench_function("pawn_structure BK1", |b| {
         b.iter(|| black_box(board.score_pawn_structure()))
     });
 }
 
+fn bench_perft(c: &mut Criterion) {
+    let mut board = Board::default();
+    c.bench_function("perft 3", |b| b.iter(|| black_box(board.perft(3))));
+}
+
 criterion_group!(
     benches,
     bench_move_gen,
+    bench_legal_move_gen,
+    bench_perft,
     bench_in_check,
     bench_mobility,
     bench_pawn_structure
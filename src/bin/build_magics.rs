@@ -0,0 +1,55 @@
+// generate magics_generated.rs
+//
+//    $ cargo run --release --bin build_magics > src/magics_generated.rs
+
+use mateus::bitmaps::{BISHOP_DIRS, ROOK_DIRS, slider_attacks, slider_mask};
+
+// trial and error - a magic is good if no two blocker sets with different moves share an index
+fn find_magic(frm: usize, dirs: &[(isize, isize); 4]) -> u64 {
+    let mask = slider_mask(frm, dirs);
+    let shift = 64 - mask.count_ones();
+
+    let mut blockers = vec![];
+    let mut b: u64 = 0;
+    loop {
+        blockers.push((b, slider_attacks(frm, b, dirs)));
+        b = b.wrapping_sub(mask) & mask;
+        if b == 0 {
+            break;
+        }
+    }
+
+    let mut table = vec![None; blockers.len()];
+    loop {
+        // sparse random numbers make better magics
+        let magic = rand::random::<u64>() & rand::random::<u64>() & rand::random::<u64>();
+        if (mask.wrapping_mul(magic) >> 56).count_ones() < 6 {
+            continue;
+        }
+        table.iter_mut().for_each(|x| *x = None);
+        let ok = blockers.iter().all(|&(b, moves)| {
+            let i = (b.wrapping_mul(magic) >> shift) as usize;
+            match table[i] {
+                None => {
+                    table[i] = Some(moves);
+                    true
+                }
+                Some(m) => m == moves,
+            }
+        });
+        if ok {
+            return magic;
+        }
+    }
+}
+
+fn magic_array(label: &str, dirs: &[(isize, isize); 4]) {
+    let a: Vec<u64> = (0..64).map(|frm| find_magic(frm, dirs)).collect();
+    println!("pub const {label}: [u64; 64] = {:?};", a);
+}
+
+fn main() {
+    println!("// Magic numbers generated by bin/build_magics.rs\n");
+    magic_array("ROOK_MAGICS", &ROOK_DIRS);
+    magic_array("BISHOP_MAGICS", &BISHOP_DIRS);
+}
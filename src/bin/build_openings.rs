@@ -1,7 +1,7 @@
 // Generate openings.rs
 
 use mateus::Game;
-use mateus::misc::str2move;
+use mateus::mgen::Move;
 use std::collections::hash_map::HashMap;
 
 #[rustfmt::skip]
@@ -69,7 +69,7 @@ const OPENINGS: [&str;107]=[
 
 fn main() {
     let mut game = Game::default();
-    let mut h: HashMap<u64, Vec<(u8, u8)>> = HashMap::new();
+    let mut h: HashMap<u64, Vec<String>> = HashMap::new();
     let mut log = vec![];
     for s in &OPENINGS {
         if &s[0..1] == "#" {
@@ -79,31 +79,27 @@ fn main() {
             continue;
         }
 
-        let Some((frm, to)) = str2move(s) else {
+        let Ok(m) = Move::from_uci(&game.board, s) else {
             panic!("Not a legal move");
         };
-        let moves = game.legal_moves();
-        let Some(m) = moves.iter().find(|m| (m.frm(), m.to()) == (frm, to)) else {
-            panic!("Not a legal move");
-        };
-        h.entry(game.board.hash)
-            .and_modify(|x| {
-                if !x.iter().any(|(frm, to)| (*frm, *to) == (m.frm(), m.to())) {
-                    x.push((m.frm(), m.to()))
-                }
-            })
-            .or_insert(vec![(m.frm(), m.to())]);
-        game.board.update(m);
-        log.push(*m);
+        let uci = m.to_uci(false);
+        let v = h.entry(game.board.hash).or_default();
+        if !v.contains(&uci) {
+            v.push(uci)
+        }
+        game.board.update(&m);
+        log.push(m);
     }
     println!("// file generated by bin/build_openings.rs");
     println!("// copy to src/openings.rs \n");
-    let s = "pub fn library_moves(hash: u64) -> &'static [(usize, usize)] {
+    let s = "pub fn library_moves(hash: u64) -> &'static [&'static str] {
     match hash {";
     println!("{s}");
+    let mut h: Vec<_> = h.into_iter().collect();
+    h.sort();
     for (k, v) in h {
-        println!("        {k} => &{:?}[0..{}],", v, v.len())
+        println!("        {k} => &{:?},", v)
     }
-    let s = "        _ => &[][0..0], } }";
+    let s = "        _ => &[], } }";
     println!("{s}");
 }
@@ -6,13 +6,11 @@
 
 use clap::Parser;
 use csv::Writer;
-use flate2::read::GzDecoder;
 use mateus::Game;
-use regex::Regex;
+use mateus::pgn::{PgnError, PgnGame, PgnReader};
 use std::collections::HashMap;
 use std::fs::File;
-use std::io::{self};
-use std::io::{BufRead, BufReader, Result};
+use std::io::{self, Result};
 use std::path::{Path, PathBuf};
 
 #[derive(Parser, Debug)]
@@ -157,152 +155,85 @@ fn static_comment(s: &str) -> &'static str {
     }
 }
 
-fn parse_moves(fg: &mut FicsG, line: String) -> (Vec<(u8, u8)>, Vec<Vec<u8>>, &'static str) {
-    let result = "000"; // dummy
-    let mut lmoves = Vec::new();
-    let mut lfens = Vec::new();
-    let mut game = Game::default();
+// FICS game from the PGN tags - None if the game is not wanted
+fn fics_game(pgn: &PgnGame) -> Option<FicsG> {
+    let tag = |key: &str| pgn.tag(key).unwrap_or_default().to_string();
+    let num = |key: &str| pgn.tag(key).and_then(|v| v.parse().ok()).unwrap_or(0);
+    let mut g = FicsG::new();
+    g.event = tag("Event");
+    g.site = tag("Site");
+    g.game_no = num("FICSGamesDBGameNo");
+    g.white = tag("White");
+    g.black = tag("Black");
+    g.white_elo = num("WhiteElo");
+    g.black_elo = num("BlackElo");
+    g.white_rd = tag("WhiteRD");
+    g.black_rd = tag("BlackRD");
+    g.black_is_comp = tag("BlackIsComp");
+    g.time_control = tag("TimeControl");
+    g.date = tag("Date");
+    g.time = tag("Time");
+    g.white_clock = tag("WhiteClock");
+    g.black_clock = tag("BlackClock");
+    g.eco = tag("ECO");
+    g.ply_count = num("PlyCount");
+    g.result = static_outcome(pgn.tag("Result").unwrap_or_default());
 
-    let re_move_number = Regex::new(r"^\d+\.$").unwrap();
-    let re_comment = Regex::new(r"\{([^}]*)\}").unwrap();
-
-    let mut comments = Vec::new();
-    for caps in re_comment.captures_iter(line.as_str()) {
-        comments.push(caps[1].to_string());
-    }
-
-    if comments.len() == 1 {
-        let s = comments[0].as_str();
+    // FICS puts how the game ended in a comment after the last move
+    let comment = pgn
+        .moves
+        .last()
+        .and_then(|m| m.comments.last())
+        .or(pgn.comments.last());
+    if let Some(s) = comment {
         if !conclusive(s) {
-            print!("{s}");
-            return (lmoves, lfens, result);
+            println!("{s}");
+            return None;
         }
-        fg.comment = static_comment(s);
-    } else if comments.len() > 1 {
-        println!("Multiple comments: {line}");
-        return (lmoves, lfens, result);
+        g.comment = static_comment(s);
+    }
+    if static_outcome(&pgn.result) != g.result {
+        println!("Result mismatch in game no {}", g.game_no);
+        return None;
     }
 
-    let line = re_comment.replace_all(line.as_str(), "").to_string();
-
-    //println!("Comments: {comments:?}");
-    //println!("CLine: {line}");
-
-    for s in line.split_whitespace() {
-        if re_move_number.is_match(s) || re_comment.is_match(s) {
-            continue;
-        }
-        if matches!(s, "0-1" | "1-0" | "1/2-1/2") {
-            return (lmoves, lfens, static_outcome(s));
-        }
-
-        let moves = game.legal_moves();
-        let alg_labels: Vec<_> = moves.iter().map(|m| game.move2label(m, &moves)).collect();
-        match alg_labels.iter().position(|r| r == s) {
-            Some(index) => {
-                game.make_move(moves[index]);
-                lmoves.push((moves[index].frm(), moves[index].to()));
-                lfens.push(game.board.to_csv());
-            }
-            None => {
-                println!("Logged moves: {line}");
-                println!("{game}");
-                println!("Computed moves: {:?}", alg_labels);
-                print!("Logged move '{}' not valid?", s);
-                break;
-            }
-        }
+    let mut game = Game::new(pgn.start());
+    for m in pgn.mainline() {
+        game.make_move(m);
+        g.moves.push((m.frm(), m.king_to()));
+        g.fens.push(game.board.to_csv());
     }
-    (lmoves, lfens, result)
+    Some(g)
 }
 
 fn read_games(fname: &str, min_elo: usize) -> io::Result<Vec<FicsG>> {
-    //let input = File::open(fname)?;
-    //let buffered = io::BufReader::new(input);
-    let input = File::open(fname).expect("Open file");
-    let buffered = BufReader::new(input);
-    let decoder = GzDecoder::new(buffered);
-    let buffered = BufReader::new(decoder);
-
     let mut games = Vec::new();
-    let mut current_game = FicsG::new();
-    let mut in_moves_section = false;
-
-    for line in buffered.lines() {
-        let line = line?;
-        if line.starts_with('[') {
-            let parts: Vec<&str> = line[1..line.len() - 1].splitn(2, ' ').collect();
-            let key = parts[0];
-            let value = parts[1].trim_matches('"');
-            match key {
-                "Event" => current_game.event = value.to_string(),
-                "Site" => current_game.site = value.to_string(),
-                "FICSGamesDBGameNo" => {
-                    current_game.game_no = value.parse::<usize>().expect("expected usize")
-                }
-                "White" => current_game.white = value.to_string(),
-                "Black" => current_game.black = value.to_string(),
-                "WhiteElo" => {
-                    current_game.white_elo = value.parse::<usize>().expect("expected usize")
-                }
-                "BlackElo" => {
-                    current_game.black_elo = value.parse::<usize>().expect("expected usize")
-                }
-                "WhiteRD" => current_game.white_rd = value.to_string(),
-                "BlackRD" => current_game.black_rd = value.to_string(),
-                "BlackIsComp" => current_game.black_is_comp = value.to_string(),
-                "TimeControl" => current_game.time_control = value.to_string(),
-                "Date" => current_game.date = value.to_string(),
-                "Time" => current_game.time = value.to_string(),
-                "WhiteClock" => current_game.white_clock = value.to_string(),
-                "BlackClock" => current_game.black_clock = value.to_string(),
-                "ECO" => current_game.eco = value.to_string(),
-                "PlyCount" => {
-                    current_game.ply_count = value.parse::<usize>().expect("expected usize")
-                }
-                "Result" => {
-                    current_game.result = match value {
-                        "0-1" => "0-1",
-                        "1-0" => "1-0",
-                        "1/2-1/2" => "1/2-1/2",
-                        _ => panic!("Unexpected game Result"),
-                    }
-                }
-                _ => {}
+    for pgn in PgnReader::open(fname)? {
+        let mut g = match pgn {
+            Ok(pgn) => match fics_game(&pgn) {
+                Some(g) => g,
+                None => continue,
+            },
+            Err(PgnError::Io(e)) => return Err(e),
+            Err(e) => {
+                println!("; Ignoring game: {e}");
+                continue;
             }
-        } else if !line.is_empty() && !line.starts_with(' ') {
-            in_moves_section = true;
-            let (moves, fens, res) = parse_moves(&mut current_game, line);
-            if res == "000" {
-                println!("; Ignoring game no {};", current_game.game_no);
-            } else if res != current_game.result {
-                println!("Result mismatch in game no {}", current_game.game_no);
-            } else {
-                current_game.moves.extend(moves);
-                current_game.fens.extend(fens);
+        };
+        if matches!(g.result, "1-0" | "0-1") && g.white_elo >= min_elo && g.black_elo >= min_elo {
+            // add final outcome to positions
+            let z = match g.result {
+                "0-1" => 0,
+                "1-0" => 1,
+                "1/2-1/2" => 2,
+                _ => panic!("Unexpected game Result"),
+            };
+            for v in &mut g.fens {
+                v.push(z);
             }
-        } else if line.is_empty() && in_moves_section {
-            if matches!(current_game.result, "1-0" | "0-1")
-                && current_game.white_elo >= min_elo
-                && current_game.black_elo >= min_elo
-            {
-                // add final outcome to positions
-                let z = match current_game.result {
-                    "0-1" => 0,
-                    "1-0" => 1,
-                    "1/2-1/2" => 2,
-                    _ => panic!("Unexpected game Result"),
-                };
-                for v in &mut current_game.fens {
-                    v.push(z);
-                }
-                games.push(current_game);
-            }
-            current_game = FicsG::new();
-            in_moves_section = false;
+            games.push(g);
         }
     }
-
     Ok(games)
 }
 
@@ -5,12 +5,13 @@
 
 use ::std::time::Instant;
 use clap::Parser;
-use mateus::Game;
 use mateus::benchmark;
 use mateus::mgen::{Board, Move};
-use mateus::misc::str2move;
 use mateus::openings::library_moves;
+use mateus::polyglot::Book;
+use mateus::transposition::DEFAULT_HASH_MB;
 use mateus::val::*;
+use mateus::{Game, pv2str};
 use rand::random;
 use std::collections::hash_map::HashMap;
 use std::io;
@@ -21,6 +22,18 @@ struct Args {
     #[arg(short, long, default_value_t = 1000000)]
     ///break off search threshold - positions generated
     n: usize,
+    #[arg(short, long, default_value_t = 30)]
+    ///max depth of regular search
+    d: u16,
+    #[arg(short, long, default_value_t = 1)]
+    ///multi pv - number of best moves to score exactly
+    p: usize,
+    #[arg(short, long, default_value_t = 1)]
+    ///number of search threads
+    t: usize,
+    #[arg(short, long, default_value_t = DEFAULT_HASH_MB)]
+    ///transposition table size (MB)
+    s: usize,
     #[arg(short, long, default_value_t = -1)]
     ///number of moves before stopping
     m: isize,
@@ -42,15 +55,20 @@ struct Args {
     #[arg(short, long, default_value_t = String::from(ROOT_FEN))]
     ///fen board - start position
     f: String,
+    #[arg(short, long)]
+    ///polyglot opening book (.bin) - replaces the built-in library
+    o: Option<String>,
 }
 
-fn pick_move(game: &mut Game, moves: &[Move]) -> Vec<(Move, i16)> {
-    let label = if game.turn().is_white() {
-        "White"
-    } else {
-        "Black"
-    };
+// empty if a take back (u) or redo (r) hands the move to the computer
+fn pick_move(game: &mut Game, players: &HashMap<Colour, bool>) -> Vec<(Move, i16)> {
     loop {
+        let label = if game.turn().is_white() {
+            "White"
+        } else {
+            "Black"
+        };
+        let moves = &game.legal_moves()[..];
         println!("Your Move ({label}):");
         let s = get_input();
         match s.as_str() {
@@ -62,75 +80,76 @@ fn pick_move(game: &mut Game, moves: &[Move]) -> Vec<(Move, i16)> {
                 }
                 println!();
             }
-            _ => {
-                let Some((frm, to)) = str2move(s.as_str()) else {
-                    println!("Not valid");
-                    continue;
-                };
-                let l: Vec<_> = moves
-                    .iter()
-                    .filter(|m| (m.frm(), m.to()) == (frm, to))
-                    .collect();
-                match l.len() {
-                    0 => println!("Not legal"),
-                    1 => return vec![(*l[0], 0)],
-                    _ => {
-                        let mut n;
-                        let label = format!("pick a number [0-{}]", l.len() - 1);
-                        loop {
-                            for (i, m) in l.iter().enumerate() {
-                                println!("[{i}] Move: {m}");
-                            }
-                            n = get_number::<usize>(label.as_str());
-                            if n < l.len() {
-                                break;
-                            }
-                        }
-                        return vec![(*l[n], 0)];
+            // take back / replay moves until it is a human's turn again
+            "u" | "r" => {
+                let step = |game: &mut Game| {
+                    if s == "u" {
+                        game.undo_move()
+                    } else {
+                        game.redo_move()
                     }
+                };
+                if step(game).is_none() {
+                    println!("No move to {}", if s == "u" { "take back" } else { "redo" });
+                    continue;
+                }
+                while !players[&game.turn()] && step(game).is_some() {}
+                println!("{game}");
+                if !players[&game.turn()] {
+                    return vec![];
+                }
+            }
+            _ => {
+                // coordinates ("e2e4", "e7e8n") or SAN ("e4", "Nf3", "O-O")
+                match Move::from_uci(&game.board, &s) {
+                    Ok(m) => return vec![(m, 0)],
+                    Err(_) => match game.parse_san(&s) {
+                        Ok(m) => return vec![(m, 0)],
+                        Err(e) => println!("{e}"),
+                    },
                 }
             }
         }
     }
 }
 
-fn check_game_over(game: &Game, moves: &[Move], half_moves: isize) -> String {
-    if game.board.rep_count() >= 3 {
-        "1/2-1/2 Draw by repetition".to_string()
-    } else if game.board.half_moves() >= 100 {
-        "1/2-1/2 Draw by the 50-move rule".to_string()
+fn check_game_over(game: &Game, half_moves: isize) -> String {
+    if let Some(result) = game.outcome() {
+        result.to_string()
     } else if half_moves != -1 && half_moves <= game.board.full_move_count as isize {
         format!("stopping after {} move(s)", game.board.full_move_count)
-    } else if moves.is_empty() {
-        if game.in_check(game.turn()) {
-            if game.turn().is_white() { "0-1" } else { "1-0" }
-        } else {
-            "1/2-1/2 Draw"
-        }
-        .to_string()
     } else {
         "".to_string()
     }
 }
 
-fn benchmark(verbose: bool, search_threshold: usize, tname: &str, tpos: &[(&str, &str)]) {
-    println!("{} Test - search threshold: {search_threshold}", tname);
+fn benchmark(args: &Args, tname: &str, tpos: &[(&str, &str)]) {
+    let (verbose, search_threshold, max_depth) = (args.v, args.n, args.d);
+    let (multipv, threads, hash_mb) = (args.p, args.t, args.s);
+    println!(
+        "{tname} Test - search threshold: {search_threshold}, max depth: {max_depth}, multi pv: {multipv}, threads: {threads}, hash: {hash_mb} MB"
+    );
     let mut correct: Vec<usize> = vec![];
     let mut points: f64 = 0.0;
     let mut n_searched: usize = 0;
+    let mut researches: usize = 0;
     let start = Instant::now();
     for (i, (fen, label)) in tpos.iter().enumerate() {
         let Ok(board) = Board::from_fen(fen) else {
             println!("Bad fen: {fen}");
             continue;
         };
-        let mut game = Game::new(board);
+        let mut game = Game::with_hash_size(board, hash_mb);
+        game.multipv = multipv;
+        game.threads = threads;
         let moves = game.legal_moves();
         game.n_searched = 0;
 
-        let l = game.score_moves(&moves, search_threshold, verbose);
+        let res = game.score_moves(&moves, search_threshold, max_depth, verbose);
+        let l = &res.moves;
         let (best, score) = l[0];
         n_searched += game.n_searched;
+        researches += res.researches;
         let clabel = game.move2label(&best, &moves);
         let colour = if game.board.colour.is_white() {
             "white"
@@ -144,8 +163,8 @@ fn benchmark(verbose: bool, search_threshold: usize, tname: &str, tpos: &[(&str,
                 println!("{i}/{}: {m} {}/{score}", l.len(), m.val);
             }
             let clabel = game.move2label(m, &moves);
-            if i < 4 && (*label).contains(clabel.as_str()) {
-                // note - only the best move is accurately scored (pruning)
+            if i < 4 && i < res.pvs.len() && (*label).contains(clabel.as_str()) {
+                // note - only the multi pv moves are accurately scored (pruning)
                 points += match i {
                     0 => 1.0,
                     1 => 0.5,
@@ -156,11 +175,16 @@ fn benchmark(verbose: bool, search_threshold: usize, tname: &str, tpos: &[(&str,
             }
         }
         println!(
-            "Position {:>2}; Searched: {:>9}, Score: {score:>5 }, Move ({colour}): {} = {clabel:>4 }; Expected: {label}\n",
+            "Position {:>2}; Searched: {:>9}, Score: {score:>5 }, Move ({colour}): {} = {clabel:>4 }; Expected: {label}",
             i + 1,
             game.n_searched,
             best
         );
+        println!("Depth: {}, Re-searches: {}", res.depth, res.researches);
+        for (i, pv) in res.pvs.iter().enumerate() {
+            println!("PV {}: {:>5} {}", i + 1, l[i].1, pv2str(pv));
+        }
+        println!();
         if (*label).contains(clabel.as_str()) {
             //if clabel.as_str() == *label {
             correct.push(i + 1);
@@ -176,53 +200,55 @@ fn benchmark(verbose: bool, search_threshold: usize, tname: &str, tpos: &[(&str,
             0
         };
         println!(
-            "Search total: {n_searched:}; Time {} ms => {speed:} nodes/ms ",
+            "Search total: {n_searched:}; Re-searches: {researches}; Time {} ms => {speed:} nodes/ms ",
             (Instant::now() - start).as_millis() as usize,
         );
     }
 }
 
-fn play(
-    players: HashMap<Colour, bool>,
-    verbose: bool,
-    search_threshold: usize,
-    half_moves: isize,
-    library_bypass: bool,
-    fen: &str,
-) -> Result<(), String> {
-    let mut game = Game::new(Board::from_fen(fen)?);
-
+fn play(mut game: Game, players: HashMap<Colour, bool>, book: Option<Book>, args: &Args) {
+    let (verbose, search_threshold, max_depth) = (args.v, args.n, args.d);
+    let (half_moves, library_bypass) = (args.m, args.l);
     let mut tot = 0;
-    let mut moves = game.legal_moves();
 
     let start = Instant::now();
     loop {
-        let msg = check_game_over(&game, &moves, half_moves);
+        let msg = check_game_over(&game, half_moves);
         if !msg.is_empty() {
             println!("{}", msg);
             std::process::exit(1);
         }
 
+        let moves = game.legal_moves();
         let l = if players[&game.turn()] {
-            pick_move(&mut game, &moves)
+            pick_move(&mut game, &players)
         } else {
             // try library 1st - compute if not there
             let lmoves = library_moves(game.board.hash);
-            if !library_bypass && !lmoves.is_empty() {
+            let bmove = match &book {
+                Some(book) if !library_bypass => book.pick(&mut game),
+                _ => None,
+            };
+            if let Some(m) = bmove {
+                println!("Book Move {m} ");
+                vec![(m, 0i16)]
+            } else if book.is_none() && !library_bypass && !lmoves.is_empty() {
                 if verbose {
                     println!("#library moves from {}: {}", game.board.hash, lmoves.len());
                     println!("{:?}", lmoves);
                 };
                 let i = random::<u32>() % lmoves.len() as u32;
-                let (frm, to) = lmoves[i as usize];
-                if let Some(m) = moves.iter().find(|m| (m.frm(), m.to()) == (frm, to)) {
-                    println!("Library Move {} ", m);
-                    vec![(*m, 0i16)]
-                } else {
+                let Ok(m) = Move::from_uci(&game.board, lmoves[i as usize]) else {
                     panic!("Not a valid library move")
-                }
+                };
+                println!("Library Move {} ", m);
+                vec![(m, 0i16)]
             } else {
-                game.score_moves(&moves, search_threshold, verbose)
+                let res = game.score_moves(&moves, search_threshold, max_depth, verbose);
+                if verbose {
+                    println!("PV: {}", pv2str(res.pv()));
+                }
+                res.moves
             }
         };
 
@@ -242,20 +268,23 @@ fn play(
                 speed
             );
             println!(
-                "hash size r {} t {} ",
-                game.board.rep.len(),
+                "half moves {} hash size {} ",
+                game.board.half_moves(),
                 game.ttable.len(),
             );
             for (i, (m, score)) in l.iter().enumerate() {
                 println!("{}/{}: {} {}/{}", i, moves.len(), m, m.val, score);
             }
         }
-        let (m, score) = l[0];
+        let Some(&(m, score)) = l.first() else {
+            continue;
+        };
 
+        // the moves of the position now - a take back may have changed it
+        let moves = game.legal_moves();
         let label = game.move2label(&m, &moves);
         game.make_move(m);
         println!("{game}");
-        moves = game.legal_moves();
         println!("{}. {label}", game.board.move_number());
 
         if verbose {
@@ -274,18 +303,6 @@ fn play(
     }
 }
 
-pub fn get_number<T: std::str::FromStr>(msg: &str) -> T {
-    loop {
-        println!("{msg}");
-        let input: String = get_input();
-        if let Ok(num) = input.trim().parse::<T>() {
-            return num;
-        } else {
-            println!("Invalid input. Please enter a valid number.");
-        };
-    }
-}
-
 fn get_input() -> String {
     let mut s = String::new();
     io::stdin().read_line(&mut s).ok();
@@ -296,25 +313,37 @@ fn main() {
     let args = Args::parse();
 
     if args.k > 0 {
-        match args.k {
-            1 => benchmark(args.v, args.n, "Bratko-Kopec", &benchmark::BRATKO_KOPEC),
-            2 => benchmark(args.v, args.n, "Kaufman", &benchmark::KAUFMAN),
-            3 => benchmark(args.v, args.n, "Lasker", &benchmark::LASKER),
-            4 => benchmark(args.v, args.n, "Nolot", &benchmark::NOLOT),
-            5 => benchmark(args.v, args.n, "CCR One Hour", &benchmark::CCR),
-            6 => benchmark(
-                args.v,
-                args.n,
-                "Eigenmann Rapid Engine Test",
-                &benchmark::ERET,
-            ),
-            7 => benchmark(args.v, args.n, "BT-2450", &benchmark::BT2450),
-            _ => benchmark(args.v, args.n, "BT-2630", &benchmark::BT2630),
-        }
+        let (tname, tpos): (&str, &[(&str, &str)]) = match args.k {
+            1 => ("Bratko-Kopec", &benchmark::BRATKO_KOPEC),
+            2 => ("Kaufman", &benchmark::KAUFMAN),
+            3 => ("Lasker", &benchmark::LASKER),
+            4 => ("Nolot", &benchmark::NOLOT),
+            5 => ("CCR One Hour", &benchmark::CCR),
+            6 => ("Eigenmann Rapid Engine Test", &benchmark::ERET),
+            7 => ("BT-2450", &benchmark::BT2450),
+            _ => ("BT-2630", &benchmark::BT2630),
+        };
+        benchmark(&args, tname, tpos);
     } else {
+        let board = match Board::from_fen(args.f.as_str()) {
+            Ok(board) => board,
+            Err(m) => {
+                println!("Bad fen: {m}");
+                return;
+            }
+        };
+        let mut game = Game::with_hash_size(board, args.s);
+        game.multipv = args.p;
+        game.threads = args.t;
+        let book = match args.o.as_deref().map(Book::open) {
+            None => None,
+            Some(Ok(book)) => Some(book),
+            Some(Err(m)) => {
+                println!("Bad book: {m}");
+                return;
+            }
+        };
         let players = HashMap::from([(Colour::white(), args.w), (Colour::black(), args.b)]);
-        if let Err(m) = play(players, args.v, args.n, args.m, args.l, args.f.as_str()) {
-            println!("Bad fen: {m}");
-        }
+        play(game, players, book, &args);
     }
 }
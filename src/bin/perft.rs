@@ -0,0 +1,52 @@
+// Perft - count the leaf nodes of the move tree, for verifying the move generator
+// https://www.chessprogramming.org/Perft_Results
+//
+//    $ cargo run --release --bin perft -- -d 5
+//    $ cargo run --release --bin perft -- -d 4 --divide -f "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1"
+
+use clap::Parser;
+use mateus::mgen::Board;
+use mateus::val::ROOT_FEN;
+use std::time::Instant;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    #[arg(short, long, default_value_t = String::from(ROOT_FEN))]
+    ///fen board - start position
+    f: String,
+    #[arg(short, long, default_value_t = 5)]
+    ///depth
+    d: u16,
+    #[arg(long, default_value_t = false)]
+    ///node count per root move
+    divide: bool,
+}
+
+fn main() {
+    let args = Args::parse();
+    let mut board = match Board::from_fen(args.f.as_str()) {
+        Ok(board) => board,
+        Err(m) => {
+            println!("Bad fen: {m}");
+            return;
+        }
+    };
+
+    let start = Instant::now();
+    let n = if args.divide {
+        let mut l = board.divide(args.d);
+        l.sort_by_key(|(m, _)| m.to_string());
+        for (m, n) in &l {
+            println!("{m}: {n}");
+        }
+        println!("Moves: {}", l.len());
+        l.iter().map(|(_, n)| n).sum()
+    } else {
+        board.perft(args.d)
+    };
+    let ms = start.elapsed().as_millis();
+    let speed = (n as u128).checked_div(ms).unwrap_or(0);
+    println!("Nodes: {n}");
+    println!("Time: {ms} ms => {speed} nodes/ms");
+}
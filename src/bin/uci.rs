@@ -11,102 +11,253 @@
 // https://www.wbec-ridderkerk.nl/html/UCIProtocol.html
 //
 
+use mateus::limits::SearchLimits;
+use mateus::mgen::{Board, Move};
+use mateus::polyglot::Book;
+use mateus::transposition::DEFAULT_HASH_MB;
+use mateus::{Game, INFINITE};
 use std::io::{self, BufRead};
-use std::sync::mpsc::{self, Sender};
 use std::sync::{
     Arc,
     atomic::{AtomicBool, Ordering},
 };
-use std::thread;
-use std::time::Duration;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
-fn main() {
-    let stdin = io::stdin();
-    let (tx, rx) = mpsc::channel();
-    let is_searching = Arc::new(AtomicBool::new(false));
-    let stop_search = Arc::new(AtomicBool::new(false));
-
-    let tx_clone = tx.clone();
-    thread::spawn(move || {
-        for line in stdin.lock().lines() {
-            let line = line.expect("Failed to read line");
-            handle_command(
-                &line,
-                tx_clone.clone(),
-                Arc::clone(&is_searching),
-                Arc::clone(&stop_search),
-            );
+fn parse_go(cmd: &str) -> SearchLimits {
+    let mut limits = SearchLimits::default();
+    let mut tokens = cmd.split_whitespace().skip(1);
+    while let Some(t) = tokens.next() {
+        match t {
+            "infinite" => limits.infinite = true,
+            "wtime" => limits.wtime = tokens.next().and_then(|v| v.parse().ok()),
+            "btime" => limits.btime = tokens.next().and_then(|v| v.parse().ok()),
+            "winc" => limits.winc = tokens.next().and_then(|v| v.parse().ok()),
+            "binc" => limits.binc = tokens.next().and_then(|v| v.parse().ok()),
+            "movestogo" => limits.movestogo = tokens.next().and_then(|v| v.parse().ok()),
+            "depth" => limits.depth = tokens.next().and_then(|v| v.parse().ok()),
+            "nodes" => limits.nodes = tokens.next().and_then(|v| v.parse().ok()),
+            "movetime" => limits.movetime = tokens.next().and_then(|v| v.parse().ok()),
+            _ => (),
         }
-    });
-
-    while let Ok(message) = rx.recv() {
-        println!("{}", message);
     }
+    limits
 }
 
-fn handle_command(
-    command: &str,
-    tx: Sender<String>,
-    is_searching: Arc<AtomicBool>,
-    stop_search: Arc<AtomicBool>,
-) {
-    match command {
-        "uci" => {
-            tx.send("id name MyChessEngine".to_string()).unwrap();
-            tx.send("id author YourName".to_string()).unwrap();
-            // option
-            tx.send("uciok".to_string()).unwrap();
-        }
-        "debug on" => {}
-        "debug off" => {}
-        "isready" => {
-            tx.send("readyok".to_string()).unwrap();
+struct Engine {
+    game: Option<Game>, // None while the search thread owns it
+    search: Option<JoinHandle<Game>>,
+    stop: Arc<AtomicBool>,
+    book: Option<Book>,
+    book_file: String,
+    chess960: bool, // castling is sent as king takes rook
+}
+
+impl Engine {
+    fn new() -> Self {
+        let game = Game::default();
+        let stop = Arc::clone(&game.stop);
+        Engine {
+            game: Some(game),
+            search: None,
+            stop,
+            book: None,
+            book_file: String::new(),
+            chess960: false,
         }
-        cmd if cmd.starts_with("setoption name ") => {}
-        "register later" => {}
-        cmd if cmd.starts_with("register name") => {}
-        "ucinewgame" => {
-            // Initialize a new game
+    }
+
+    // stop a running search (if any) and take back the game
+    fn game(&mut self) -> &mut Game {
+        if let Some(handle) = self.search.take() {
+            self.stop.store(true, Ordering::SeqCst);
+            self.game = Some(handle.join().expect("search thread panicked"));
         }
-        cmd if cmd.starts_with("position") => {
-            let parts: Vec<&str> = cmd.split_whitespace().collect();
-            if parts.len() > 1 && parts[1] == "startpos" {
-                // Set the board to the starting position
-            } else if parts.len() > 2 && parts[1] == "fen" {
-                // Set the board to the specified FEN position
+        self.game
+            .as_mut()
+            .expect("game is either here or in the search thread")
+    }
+
+    fn position(&mut self, cmd: &str) {
+        let parts: Vec<&str> = cmd.split_whitespace().collect();
+        let moves_index = parts.iter().position(|&x| x == "moves");
+        let board = match parts.get(1) {
+            Some(&"startpos") => Ok(Board::default()),
+            Some(&"fen") => {
+                let end = moves_index.unwrap_or(parts.len());
+                Board::from_fen(parts[2..end].join(" ").as_str())
             }
-            if let Some(moves_index) = parts.iter().position(|&x| x == "moves") {
-                // Apply moves to the board
-                for _mv in &parts[moves_index + 1..] {
-                    // Apply each move
+            _ => Err("expected startpos or fen".to_string()),
+        };
+        let board = match board {
+            Ok(board) => board,
+            Err(msg) => {
+                println!("info string bad position: {msg}");
+                return;
+            }
+        };
+
+        let game = self.game();
+        game.set_board(board);
+        if let Some(i) = moves_index {
+            for s in &parts[i + 1..] {
+                match Move::from_uci(&game.board, s) {
+                    Ok(m) => game.make_move(m),
+                    Err(e) => {
+                        println!("info string {e}");
+                        return;
+                    }
                 }
             }
         }
-        cmd if cmd.starts_with("go") => {
-            if !is_searching.load(Ordering::SeqCst) {
-                is_searching.store(true, Ordering::SeqCst);
-                let tx_clone = tx.clone();
-                let stop_search_clone = Arc::clone(&stop_search);
-                let is_searching_clone = Arc::clone(&is_searching);
-                thread::spawn(move || {
-                    // Start searching for the best move
-                    thread::sleep(Duration::from_secs(5));
-                    if !stop_search_clone.load(Ordering::SeqCst) {
-                        tx_clone.send("bestmove e2e4".to_string()).unwrap(); // Example response
-                    }
-                    is_searching_clone.store(false, Ordering::SeqCst);
-                });
+    }
+
+    // setoption name <id> [value <x>]
+    fn setoption(&mut self, cmd: &str) {
+        let rest = &cmd["setoption name ".len()..];
+        let (name, value) = match rest.split_once(" value ") {
+            Some((name, value)) => (name.trim(), value.trim()),
+            None => (rest.trim(), ""),
+        };
+        match name.to_lowercase().as_str() {
+            "multipv" => match value.parse::<usize>() {
+                Ok(n) => self.game().multipv = n.clamp(1, 64),
+                Err(_) => println!("info string bad MultiPV value: {value}"),
+            },
+            "hash" => match value.parse::<usize>() {
+                Ok(mb) => self.game().set_hash_size(mb.clamp(1, 65536)),
+                Err(_) => println!("info string bad Hash value: {value}"),
+            },
+            "threads" => match value.parse::<usize>() {
+                Ok(n) => self.game().threads = n.clamp(1, 256),
+                Err(_) => println!("info string bad Threads value: {value}"),
+            },
+            "bookfile" => {
+                self.book_file = value.to_string();
+                self.load_book();
             }
+            "uci_chess960" => match value.parse::<bool>() {
+                Ok(b) => self.chess960 = b,
+                Err(_) => println!("info string bad UCI_Chess960 value: {value}"),
+            },
+            _ => println!("info string unknown option: {name}"),
         }
-        "stop" => {
-            stop_search.store(true, Ordering::SeqCst);
+    }
+
+    // polyglot book
+    fn load_book(&mut self) {
+        self.book = None;
+        if self.book_file.is_empty() || self.book_file == "<empty>" {
+            return;
         }
-        "ponderhit" => {}
-        "quit" => {
-            std::process::exit(0);
+        match Book::open(&self.book_file) {
+            Ok(book) => {
+                println!("info string book {} entries", book.len());
+                self.book = Some(book);
+            }
+            Err(msg) => println!("info string bad book: {msg}"),
         }
-        _ => {
-            // unknown command
+    }
+
+    fn go(&mut self, cmd: &str) {
+        let limits = parse_go(cmd);
+        // a search still running (go infinite without stop) is stopped first
+        self.game();
+        let mut game = self.game.take().expect("search thread joined");
+        if !limits.infinite
+            && let Some(m) = self.book.as_ref().and_then(|book| book.pick(&mut game))
+        {
+            println!("bestmove {}", m.to_uci(self.chess960));
+            self.game = Some(game);
+            return;
+        }
+        self.stop.store(false, Ordering::SeqCst);
+        let stop = Arc::clone(&self.stop);
+        let chess960 = self.chess960;
+
+        self.search = Some(thread::spawn(move || {
+            let start = Instant::now();
+            let moves = game.legal_moves();
+            let res = game.search(&moves, &limits, |res| {
+                for (i, pv) in res.pvs.iter().enumerate() {
+                    let pv: Vec<String> = pv.iter().map(|m| m.to_uci(chess960)).collect();
+                    println!(
+                        "info multipv {} depth {} score {} nodes {} time {} pv {}",
+                        i + 1,
+                        res.depth,
+                        uci_score(res.moves[i].1),
+                        res.n_searched,
+                        start.elapsed().as_millis(),
+                        pv.join(" ")
+                    );
+                }
+                true
+            });
+
+            // in infinite mode bestmove must not be sent before the gui says stop
+            while limits.infinite && !stop.load(Ordering::SeqCst) {
+                thread::sleep(Duration::from_millis(5));
+            }
+            match res.best() {
+                Some((m, _)) => println!("bestmove {}", m.to_uci(chess960)),
+                None => println!("bestmove 0000"),
+            }
+            game
+        }));
+    }
+}
+
+fn uci_score(score: i16) -> String {
+    if score >= INFINITE - 1000 {
+        let plies = (INFINITE - score) as i32;
+        format!("mate {}", (plies + 1) / 2)
+    } else if score <= -INFINITE + 1000 {
+        let plies = (INFINITE + score) as i32;
+        format!("mate -{}", plies / 2)
+    } else {
+        format!("cp {score}")
+    }
+}
+
+fn main() {
+    let mut engine = Engine::new();
+    for line in io::stdin().lock().lines() {
+        let line = line.expect("Failed to read line");
+        let cmd = line.trim();
+        match cmd {
+            "uci" => {
+                println!("id name Mateus {}", env!("CARGO_PKG_VERSION"));
+                println!("id author Jesper Olsen");
+                println!("option name Hash type spin default {DEFAULT_HASH_MB} min 1 max 65536");
+                println!("option name MultiPV type spin default 1 min 1 max 64");
+                println!("option name BookFile type string default <empty>");
+                println!("option name Threads type spin default 1 min 1 max 256");
+                println!("option name UCI_Chess960 type check default false");
+                println!("uciok");
+            }
+            "debug on" | "debug off" => {}
+            "isready" => println!("readyok"),
+            cmd if cmd.starts_with("setoption name ") => engine.setoption(cmd),
+            "register later" => {}
+            cmd if cmd.starts_with("register name") => {}
+            "ucinewgame" => {
+                let game = engine.game();
+                game.set_board(Board::default());
+                game.ttable.clear();
+            }
+            cmd if cmd.starts_with("position") => engine.position(cmd),
+            cmd if cmd.starts_with("go") => engine.go(cmd),
+            "stop" => {
+                engine.stop.store(true, Ordering::SeqCst);
+            }
+            "ponderhit" => {}
+            "quit" => {
+                engine.game();
+                break;
+            }
+            _ => {
+                // unknown command
+            }
         }
     }
 }
@@ -1,6 +1,6 @@
+use crate::magics_generated::{BISHOP_MAGICS, ROOK_MAGICS};
 use crate::val::Colour;
 
-pub static BM_BLOCKED: [[u64; 64]; 64] = bm_queen_blockers();
 pub static BM_QUEEN_MOVES: [u64; 64] = bm_queen_moves();
 pub static BM_BISHOP_MOVES: [u64; 64] = bm_bishop_moves();
 pub static BM_ROOK_MOVES: [u64; 64] = bm_rook_moves();
@@ -9,25 +9,211 @@ pub static BM_KING_MOVES: [u64; 64] = bm_king_moves();
 pub static BM_PAWN_CAPTURES: [[u64; 64]; 2] = bm_pawn_captures();
 pub static BM_PAWN_STEP1: [[u64; 64]; 2] = bm_pawn_step1();
 pub static BM_PAWN_STEP2: [[u64; 64]; 2] = bm_pawn_step2();
+pub static BM_BETWEEN: [[u64; 64]; 64] = bm_between();
+pub static BM_LINE: [[u64; 64]; 64] = bm_line();
+
+// Sliding piece moves are looked up with fancy magic bitboards -
+// https://www.chessprogramming.org/Magic_Bitboards
+// The blockers on a rook / bishop's lines are hashed (multiply by a magic number and shift)
+// to an index into a table of precomputed moves. The magic numbers are found by
+// bin/build_magics.rs, the tables are built at compile time.
+
+pub const ROOK_DIRS: [(isize, isize); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+pub const BISHOP_DIRS: [(isize, isize); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+
+const ROOK_TABLE_SIZE: usize = table_size(&ROOK_DIRS);
+const BISHOP_TABLE_SIZE: usize = table_size(&BISHOP_DIRS);
+static ROOK_LOOKUP: [Magic; 64] = magics(&ROOK_MAGICS, &ROOK_DIRS);
+static BISHOP_LOOKUP: [Magic; 64] = magics(&BISHOP_MAGICS, &BISHOP_DIRS);
+// ~100k rook entries - more work than the const evaluator expects
+#[allow(long_running_const_eval)]
+static ROOK_TABLE: [u64; ROOK_TABLE_SIZE] = attack_table(&ROOK_LOOKUP, &ROOK_DIRS);
+#[allow(long_running_const_eval)]
+static BISHOP_TABLE: [u64; BISHOP_TABLE_SIZE] = attack_table(&BISHOP_LOOKUP, &BISHOP_DIRS);
+
+struct Magic {
+    mask: u64,
+    magic: u64,
+    shift: u32,
+    offset: usize,
+}
+
+impl Magic {
+    #[inline(always)]
+    const fn index(&self, bm_board: u64) -> usize {
+        self.offset + ((bm_board & self.mask).wrapping_mul(self.magic) >> self.shift) as usize
+    }
+}
+
+/// Rook moves from frm, up to and including the first piece in each direction
+#[inline(always)]
+pub fn rook_attacks(frm: usize, bm_board: u64) -> u64 {
+    ROOK_TABLE[ROOK_LOOKUP[frm].index(bm_board)]
+}
+
+#[inline(always)]
+pub fn bishop_attacks(frm: usize, bm_board: u64) -> u64 {
+    BISHOP_TABLE[BISHOP_LOOKUP[frm].index(bm_board)]
+}
+
+#[inline(always)]
+pub fn queen_attacks(frm: usize, bm_board: u64) -> u64 {
+    rook_attacks(frm, bm_board) | bishop_attacks(frm, bm_board)
+}
+
+/// Slow version of the magic lookup - step along each direction until a piece is hit
+pub const fn slider_attacks(frm: usize, bm_board: u64, dirs: &[(isize, isize); 4]) -> u64 {
+    let mut b = 0;
+    let mut d = 0;
+    while d < dirs.len() {
+        b |= ray(frm, dirs[d], bm_board);
+        d += 1;
+    }
+    b
+}
+
+// squares in one direction from frm, up to and including the first piece
+const fn ray(frm: usize, (dx, dy): (isize, isize), bm_board: u64) -> u64 {
+    let mut b = 0;
+    let mut x = (frm / 8) as isize + dx;
+    let mut y = (frm % 8) as isize + dy;
+    while x >= 0 && x <= 7 && y >= 0 && y <= 7 {
+        b |= 1 << (x * 8 + y);
+        if bm_board & 1 << (x * 8 + y) != 0 {
+            break;
+        }
+        x += dx;
+        y += dy;
+    }
+    b
+}
 
-///valid moves from a given square assuming one square is blocked..
-const fn bm_queen_blockers() -> [[u64; 64]; 64] {
+///squares strictly between two squares on a common line - 0 if not on a line
+const fn bm_between() -> [[u64; 64]; 64] {
     let mut bm = [[0; 64]; 64];
+    let mut frm = 0;
+    while frm < 64 {
+        let mut d = 0;
+        while d < 4 {
+            let mut j = 0;
+            while j < 2 {
+                let dir = if j == 0 { ROOK_DIRS[d] } else { BISHOP_DIRS[d] };
+                let mut b = ray(frm, dir, 0);
+                while b != 0 {
+                    let to = b.trailing_zeros() as usize;
+                    b &= !(1 << to);
+                    bm[frm][to] = ray(frm, dir, 1 << to) & !(1 << to);
+                }
+                j += 1;
+            }
+            d += 1;
+        }
+        frm += 1;
+    }
+    bm
+}
 
+///the whole line (edge to edge) through two squares - 0 if not on a line
+const fn bm_line() -> [[u64; 64]; 64] {
+    let mut bm = [[0; 64]; 64];
     let mut frm = 0;
     while frm < 64 {
-        let mut blocked = 0;
-        while blocked < 64 {
-            if frm != blocked {
-                bm[frm][blocked] = bm_queen_moves_bb(frm, blocked);
+        let mut d = 0;
+        while d < 4 {
+            let mut j = 0;
+            while j < 2 {
+                let (dx, dy) = if j == 0 { ROOK_DIRS[d] } else { BISHOP_DIRS[d] };
+                let line = ray(frm, (dx, dy), 0) | ray(frm, (-dx, -dy), 0) | 1 << frm;
+                let mut b = ray(frm, (dx, dy), 0);
+                while b != 0 {
+                    let to = b.trailing_zeros() as usize;
+                    b &= !(1 << to);
+                    bm[frm][to] = line;
+                }
+                j += 1;
             }
-            blocked += 1;
+            d += 1;
         }
         frm += 1;
     }
     bm
 }
 
+/// Squares whose occupancy matters to a slider on frm - its lines minus the last square
+pub const fn slider_mask(frm: usize, dirs: &[(isize, isize); 4]) -> u64 {
+    let mut b = 0;
+    let mut d = 0;
+    while d < dirs.len() {
+        let (dx, dy) = dirs[d];
+        let mut x = (frm / 8) as isize + dx;
+        let mut y = (frm % 8) as isize + dy;
+        while x + dx >= 0 && x + dx <= 7 && y + dy >= 0 && y + dy <= 7 {
+            b |= 1 << (x * 8 + y);
+            x += dx;
+            y += dy;
+        }
+        d += 1;
+    }
+    b
+}
+
+const fn table_size(dirs: &[(isize, isize); 4]) -> usize {
+    let mut n = 0;
+    let mut i = 0;
+    while i < 64 {
+        n += 1 << slider_mask(i, dirs).count_ones();
+        i += 1;
+    }
+    n
+}
+
+const fn magics(numbers: &[u64; 64], dirs: &[(isize, isize); 4]) -> [Magic; 64] {
+    let mut m = [const {
+        Magic {
+            mask: 0,
+            magic: 0,
+            shift: 0,
+            offset: 0,
+        }
+    }; 64];
+    let mut offset = 0;
+    let mut i = 0;
+    while i < 64 {
+        let mask = slider_mask(i, dirs);
+        m[i] = Magic {
+            mask,
+            magic: numbers[i],
+            shift: 64 - mask.count_ones(),
+            offset,
+        };
+        offset += 1 << mask.count_ones();
+        i += 1;
+    }
+    m
+}
+
+const fn attack_table<const N: usize>(
+    magics: &[Magic; 64],
+    dirs: &[(isize, isize); 4],
+) -> [u64; N] {
+    let mut table = [0; N];
+    let mut i = 0;
+    while i < 64 {
+        // enumerate all subsets of the mask (carry-rippler)
+        let mask = magics[i].mask;
+        let mut bm_board: u64 = 0;
+        loop {
+            table[magics[i].index(bm_board)] = slider_attacks(i, bm_board, dirs);
+            bm_board = bm_board.wrapping_sub(mask) & mask;
+            if bm_board == 0 {
+                break;
+            }
+        }
+        i += 1;
+    }
+    table
+}
+
 // set_bit k: b |= 1<<k
 // clear_bit k: b &= !(1<<k)
 // is_set k: b & 1<<k
@@ -107,85 +293,6 @@ const fn bm_bishop_moves_from(frm: usize) -> u64 {
     b
 }
 
-///queen moves that are valid assuming assuming blocked square
-const fn bm_queen_moves_bb(frm: usize, blocked: usize) -> u64 {
-    let mut b = 0;
-
-    let frm_x = frm / 8;
-    let frm_y = frm % 8;
-    let blocked_x = blocked / 8;
-    let blocked_y = blocked % 8;
-
-    if frm_x == blocked_x {
-        if frm_y < blocked_y {
-            // rook blocked N
-            let mut i = blocked + 1;
-            while i % 8 > 0 {
-                b |= 1 << i;
-                i += 1;
-            }
-        } else {
-            // rook blocked S
-            let mut i = blocked as isize - 1;
-            while i % 8 < frm_y as isize && i >= 0 {
-                b |= 1 << i;
-                i -= 1;
-            }
-        }
-    } else if frm_y == blocked_y {
-        if frm_x < blocked_x {
-            // rook blocked W
-            let mut i = blocked + 8;
-            while i < 64 {
-                b |= 1 << i;
-                i += 8;
-            }
-        } else {
-            // rook blocked E
-            let mut i = blocked as isize - 8;
-            while i >= 0 {
-                b |= 1 << i;
-                i -= 8;
-            }
-        }
-    } else if frm_x.abs_diff(blocked_x) == frm_y.abs_diff(blocked_y) {
-        if frm_x > blocked_x {
-            if frm_y < blocked_y {
-                // bishop blocked NE
-                let mut i = blocked as isize - 7;
-                //while i % 8 <= 7 && i >= 0 {
-                while i % 8 > frm_y as isize && i >= 0 {
-                    b |= 1 << i;
-                    i -= 7;
-                }
-            } else {
-                // bishop blocked SE
-                let mut i = blocked as isize - 9;
-                //while i % 8 >= 0 && i >= 0 {
-                while i % 8 < frm_y as isize && i >= 0 {
-                    b |= 1 << i;
-                    i -= 9;
-                }
-            }
-        } else if frm_y % 8 < blocked_y % 8 {
-            //bishop blocked NW
-            let mut i = blocked + 9;
-            while i % 8 > frm_y && i < 64 {
-                b |= 1 << i;
-                i += 9;
-            }
-        } else {
-            //bishop blocked SW
-            let mut i = blocked + 7;
-            while i % 8 < frm_y && i < 64 {
-                b |= 1 << i;
-                i += 7;
-            }
-        }
-    }
-    b
-}
-
 const fn bm_pawn_step2() -> [[u64; 64]; 2] {
     let mut bm = [[0u64; 64]; 2];
     let mut i = 0;
@@ -221,10 +328,10 @@ const fn bm_pawn_captures() -> [[u64; 64]; 2] {
 
 const fn bm_white_pawn_captures_from(frm: usize) -> u64 {
     let mut b = 0;
-    if frm < 56 && (frm + 9) % 8 != 0 {
+    if frm < 56 && !(frm + 9).is_multiple_of(8) {
         b |= 1 << (frm + 9)
     }
-    if frm > 7 && (frm - 7) % 8 != 0 {
+    if frm > 7 && !(frm - 7).is_multiple_of(8) {
         b |= 1 << (frm - 7)
     }
     b
@@ -232,10 +339,10 @@ const fn bm_white_pawn_captures_from(frm: usize) -> u64 {
 
 const fn bm_black_pawn_captures_from(frm: usize) -> u64 {
     let mut b = 0;
-    if frm % 8 != 0 && frm < 56 {
+    if !frm.is_multiple_of(8) && frm < 56 {
         b |= 1 << (frm + 7)
     }
-    if frm % 8 != 0 && frm >= 9 {
+    if !frm.is_multiple_of(8) && frm >= 9 {
         b |= 1 << (frm - 9)
     }
     b
@@ -347,12 +454,30 @@ pub const fn bm2arr(bm: u64) -> ([u8; 64], usize) {
     (out, n)
 }
 
-pub const fn bm_blockers(frm: usize, mut b: u64) -> u64 {
-    let mut bl = 0;
-    while b != 0 {
-        let i = b.trailing_zeros();
-        bl |= BM_BLOCKED[frm][i as usize];
-        b &= !(1 << i);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_magics() {
+        let mut x: u64 = 0x9E3779B97F4A7C15;
+        for frm in 0..64 {
+            assert_eq!(slider_attacks(frm, 0, &ROOK_DIRS), BM_ROOK_MOVES[frm]);
+            assert_eq!(slider_attacks(frm, 0, &BISHOP_DIRS), BM_BISHOP_MOVES[frm]);
+            for _ in 0..100 {
+                x ^= x << 13;
+                x ^= x >> 7;
+                x ^= x << 17;
+                let bm_board = x & x.rotate_left(17); // ~1/4 of the squares occupied
+                assert_eq!(
+                    rook_attacks(frm, bm_board),
+                    slider_attacks(frm, bm_board, &ROOK_DIRS)
+                );
+                assert_eq!(
+                    bishop_attacks(frm, bm_board),
+                    slider_attacks(frm, bm_board, &BISHOP_DIRS)
+                );
+            }
+        }
     }
-    bl
 }
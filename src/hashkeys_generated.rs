@@ -792,70 +792,32 @@ pub const P2_HASH: [u64; 64] = [
     13777638845008327715,
     11996197490313707676,
 ];
-pub const NIL_HASH: [u64; 64] = [
-    9755680081138517028,
-    6910285249468807525,
-    1140399768400764309,
-    13333906449583867910,
-    11824202717031501358,
-    9843962081594670810,
-    9378596519438152072,
-    13015006821802912706,
-    7583661123753341297,
-    17957392479368056613,
-    18348615283524837967,
-    14660330752762097669,
-    9840763427418973239,
-    11056577468171611742,
-    9431880638533918790,
-    8245738493395789049,
-    3959122377176594432,
-    9264023213612268688,
-    2074753769252759754,
-    16525810308120581034,
-    17837583354505154485,
-    1019789803121899654,
-    2578244767932613583,
-    8202717795961777959,
-    11547789297352845242,
-    15841703399960820561,
-    4603556897539849299,
-    18369337959499327029,
-    16549245496497528251,
-    13413666729190195569,
-    14058036930020248220,
-    14219180106019511704,
-    13707057847500120542,
-    7255689410452069228,
-    5391657950944341050,
-    16211919883065315724,
-    3245469780352901871,
-    6627822032679703039,
-    9143415461251630686,
-    618534355578248274,
-    15036218160317338794,
-    504355521697241930,
-    12918076286659631555,
-    9798248731567488250,
-    13011103018458202159,
-    3065149520734049806,
-    2381663113320612939,
-    7694779702426328668,
-    4193123352667377149,
-    17335170008546032418,
-    16724203364999752007,
-    2264173534140491103,
-    17917453346233973501,
-    12192466428509593037,
-    12996218854998812024,
-    5101906523652992743,
-    12834129128658561493,
-    10886670484853350996,
-    10987668961366920735,
-    525071988737858177,
-    12579279243033346175,
-    781048158101197518,
-    2503235302064941830,
-    10222865063719921624,
-];
 pub const WHITE_HASH: u64 = 3832595165961311544;
+pub const CASTLE_HASH: [u64; 16] = [
+    559543822221989865,
+    3370740470934606430,
+    2241982281505416464,
+    16458567044041318222,
+    9657241570554640802,
+    9786321633533293605,
+    13897983902548843079,
+    17382255664643631478,
+    15783692583939312236,
+    10830524619768603393,
+    8810481743227436921,
+    18053856167550817017,
+    7320837114752620834,
+    10393801896401421756,
+    3291095798478916166,
+    3418035200263968829,
+];
+pub const EN_PASSANT_HASH: [u64; 8] = [
+    4663495419347656107,
+    16682630002472144766,
+    14084813953117909061,
+    2518658757120240091,
+    15668367657534480447,
+    6029729712431713272,
+    3285878147183990479,
+    230570422159063372,
+];
@@ -1,27 +1,120 @@
 pub mod benchmark;
 pub mod bitmaps;
 pub mod hashkeys_generated;
+pub mod limits;
+pub mod magics_generated;
 pub mod mgen;
 pub mod misc;
+pub mod movelist;
+pub mod movepick;
 pub mod openings;
+pub mod pgn;
+pub mod polyglot;
+pub mod random64_generated;
+pub mod san;
 pub mod transposition;
 pub mod val;
 
 use crate::Colour;
-use core::cmp::{max, min};
+use core::cmp::{Reverse, max, min};
+use limits::SearchLimits;
 use mgen::*;
+use movelist::MoveList;
+use movepick::{Heuristics, MovePicker, is_quiet, sort_by_val};
+use san::SanError;
 use std::fmt;
-use transposition::Transpositions;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Instant;
+use transposition::{Bound, DEFAULT_HASH_MB, Transpositions};
 use val::*;
 use val::{BPAWN, WPAWN};
 
-const INFINITE: i16 = 32000;
+pub const INFINITE: i16 = 32000;
+const MAX_PLY: usize = 128;
+const ASPIRATION_DELTA: i16 = 50; // initial half width of the aspiration window
+
+// root moves after one pass: lines with exact scores (best first) & the rest
+type RootLines = (Vec<(i16, Vec<Move>)>, Vec<(Move, i16)>);
+
+/// Outcome of a search - root moves sorted best first. The first pvs.len() moves
+/// have exact scores, and pvs[i] is the principal variation starting with moves[i].
+#[derive(Debug, Clone, Default)]
+pub struct SearchResult {
+    pub moves: Vec<(Move, i16)>,
+    pub pvs: Vec<Vec<Move>>,
+    pub depth: u16,
+    pub n_searched: usize,
+    pub researches: usize, // aspiration window fail lows & highs
+}
+
+impl SearchResult {
+    pub fn best(&self) -> Option<(Move, i16)> {
+        self.moves.first().copied()
+    }
+
+    pub fn pv(&self) -> &[Move] {
+        self.pvs.first().map_or(&[], |pv| pv.as_slice())
+    }
+}
+
+/// Why a game was drawn
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DrawReason {
+    Stalemate,
+    FiftyMoves,
+    Repetition, // threefold
+    InsufficientMaterial,
+}
+
+/// How a game ended - see Game::outcome
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GameResult {
+    Checkmate { winner: Colour },
+    Draw(DrawReason),
+}
+
+impl GameResult {
+    /// Result as written in PGN
+    pub fn score(&self) -> &'static str {
+        match self {
+            GameResult::Checkmate { winner } if winner.is_white() => "1-0",
+            GameResult::Checkmate { .. } => "0-1",
+            GameResult::Draw(_) => "1/2-1/2",
+        }
+    }
+}
+
+impl fmt::Display for GameResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self {
+            GameResult::Checkmate { .. } => "Checkmate",
+            GameResult::Draw(DrawReason::Stalemate) => "Draw by stalemate",
+            GameResult::Draw(DrawReason::FiftyMoves) => "Draw by the 50-move rule",
+            GameResult::Draw(DrawReason::Repetition) => "Draw by repetition",
+            GameResult::Draw(DrawReason::InsufficientMaterial) => "Draw by insufficient material",
+        };
+        write!(f, "{} {reason}", self.score())
+    }
+}
 
 pub struct Game {
     pub board: Board,
     pub n_searched: usize,
-    pub ttable: Transpositions,
+    pub ttable: Arc<Transpositions>, // shared by the search threads
+    pub stop: Arc<AtomicBool>,       // raise to break off a running search
+    pub multipv: usize,              // number of root moves to score exactly
+    pub threads: usize,              // number of search threads (lazy smp)
     end_game: bool,
+    history: Vec<Move>, // moves played
+    undone: Vec<Move>,  // taken back - for redo_move
+    pv: Vec<Vec<Move>>, // triangular pv table - pv[ply] is the best line from ply
+    heuristics: Heuristics,
+    deadline: Option<Instant>,
+    max_nodes: usize,
+    n_polls: u32,
+    aborted: bool,
 }
 
 impl Default for Game {
@@ -43,113 +136,163 @@ impl fmt::Display for Game {
     }
 }
 
-// struct MovePicker {
-//     moves: Vec<Move>,
-// }
+/// Moves separated by space, e.g. "e2e4 e7e5"
+pub fn pv2str(pv: &[Move]) -> String {
+    pv.iter()
+        .map(|m| m.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
 
-// impl MovePicker {
-//     fn new(board: &Board, kmove: Option<(u8, u8)>) -> Self {
-//         MovePicker { moves: vec![] }
-//     }
-// }
+//https://cheatography.com/davechild/cheat-sheets/chess-algebraic-notation/
+fn san_label(board: &mut Board, m: &Move, moves: &[Move]) -> String {
+    fn i2xy(i: u8) -> (u8, u8) {
+        let x = 7 - i / 8; // col
+        let y = i % 8; // row
+        (x, y)
+    }
 
-fn move_to_head(moves: &mut Vec<Move>, frmto: &(u8, u8)) {
-    if let Some(q) = moves
-        .iter()
-        .position(|m| (m.frm(), m.to()) == (frmto.0, frmto.1))
-    {
-        if q != 0 {
-            let m = moves.remove(q);
-            //let m = moves.swap_remove(q);
-            //println!("Move {} to head {}->0", m, q);
-            moves.insert(0, m);
+    let mut label = String::new();
+    if m.castle() {
+        if m.to() < m.frm() {
+            label.push_str("O-O");
+        } else {
+            label.push_str("O-O-O");
+        }
+    } else {
+        if board[m.frm() as usize].kind() == PAWN {
+            if board[m.to() as usize] != EMPTY || m.en_passant() {
+                label.push_str(&I2SQ[m.frm() as usize][0..1])
+            }
+        } else {
+            label.push_str(&board[m.frm() as usize].to_string().to_uppercase());
+        }
+
+        // If two or more pieces of the same type can move to the same sq we need to disambiguate
+        // by adding file if file is unique, otherwise row
+        let mut nx = 0;
+        let mut ny = 0;
+        let mut n = 0;
+        let (x0, y0) = i2xy(m.frm());
+        for m2 in moves {
+            if m2.to() == m.to()
+                && board[m.frm() as usize].is_officer()
+                && board[m.frm() as usize] == board[m2.frm() as usize]
+            {
+                n += 1;
+                let (x, y) = i2xy(m2.frm());
+                if x == x0 {
+                    nx += 1;
+                }
+                if y == y0 {
+                    ny += 1;
+                }
+            }
+        }
+        if n > 1 {
+            if nx > 1 && ny > 1 {
+                label.push_str(I2SQ[m.frm() as usize])
+            } else if nx <= ny {
+                label.push_str(&I2SQ[m.frm() as usize][0..1])
+            } else {
+                label.push_str(&I2SQ[m.frm() as usize][1..2])
+            }
+        }
+        if m.en_passant() || board[m.to() as usize] != EMPTY {
+            label.push('x');
+        }
+        label.push_str(I2SQ[m.to() as usize]);
+        if m.transform() {
+            label.push_str(m.promote_label())
         }
     }
+
+    board.update(m);
+    if board.in_check(board.colour) {
+        label.push(if board.legal_moves(false).is_empty() {
+            '#'
+        } else {
+            '+'
+        });
+    }
+    board.backdate(m);
+    label
 }
 
 impl Game {
     pub fn new(board: Board) -> Self {
+        Game::with_hash_size(board, DEFAULT_HASH_MB)
+    }
+
+    /// Game with a transposition table of (at most) `mb` MB
+    pub fn with_hash_size(board: Board, mb: usize) -> Self {
         //println!("size of TEntry {}", std::mem::size_of::<TEntry>());
         Game {
             board,
             n_searched: 0,
-            ttable: Transpositions::default(),
+            ttable: Arc::new(Transpositions::with_capacity_mb(mb)),
+            stop: Arc::new(AtomicBool::new(false)),
+            multipv: 1,
+            threads: 1,
             end_game: false,
+            history: vec![],
+            undone: vec![],
+            pv: vec![Vec::new(); MAX_PLY + 1],
+            heuristics: Heuristics::new(MAX_PLY),
+            deadline: None,
+            max_nodes: usize::MAX,
+            n_polls: 0,
+            aborted: false,
         }
     }
 
-    //https://cheatography.com/davechild/cheat-sheets/chess-algebraic-notation/
-    pub fn move2label(&mut self, m: &Move, moves: &[Move]) -> String {
-        fn i2xy(i: u8) -> (u8, u8) {
-            let x = 7 - i / 8; // col
-            let y = i % 8; // row
-            (x, y)
+    // helper thread for lazy smp - searches a copy of the board, shares the transposition table
+    fn helper(&self, stop: Arc<AtomicBool>) -> Game {
+        Game {
+            board: self.board.clone(),
+            n_searched: 0,
+            ttable: Arc::clone(&self.ttable),
+            stop,
+            multipv: 1,
+            threads: 1,
+            end_game: self.end_game,
+            history: vec![],
+            undone: vec![],
+            pv: vec![Vec::new(); MAX_PLY + 1],
+            heuristics: Heuristics::new(MAX_PLY),
+            deadline: None,
+            max_nodes: usize::MAX,
+            n_polls: 0,
+            aborted: false,
         }
+    }
 
-        let mut label = String::new();
-        if m.castle() {
-            if m.to() < 31 {
-                label.push_str("O-O");
-            } else {
-                label.push_str("O-O-O");
-            }
-        } else {
-            if self.board[m.frm() as usize].kind() == PAWN {
-                if self.board[m.to() as usize] != EMPTY || m.en_passant() {
-                    label.push_str(&I2SQ[m.frm() as usize][0..1])
-                }
-            } else {
-                label.push_str(&self.board[m.frm() as usize].to_string().to_uppercase());
-            }
-
-            // If two or more pieces of the same type can move to the same sq we need to disambiguate
-            // by adding file if file is unique, otherwise row
-            let mut nx = 0;
-            let mut ny = 0;
-            let mut n = 0;
-            let (x0, y0) = i2xy(m.frm());
-            for m2 in moves {
-                if m2.to() == m.to()
-                    && self.board[m.frm() as usize].is_officer()
-                    && self.board[m.frm() as usize] == self.board[m2.frm() as usize]
-                {
-                    n += 1;
-                    let (x, y) = i2xy(m2.frm());
-                    if x == x0 {
-                        nx += 1;
-                    }
-                    if y == y0 {
-                        ny += 1;
-                    }
-                }
-            }
-            if n > 1 {
-                if nx > 1 && ny > 1 {
-                    label.push_str(I2SQ[m.frm() as usize])
-                } else if nx <= ny {
-                    label.push_str(&I2SQ[m.frm() as usize][0..1])
-                } else {
-                    label.push_str(&I2SQ[m.frm() as usize][1..2])
-                }
-            }
-            if m.en_passant() || self.board[m.to() as usize] != EMPTY {
-                label.push('x');
-            }
-            label.push_str(I2SQ[m.to() as usize]);
-            if m.transform() {
-                label.push_str(m.promote_label())
-            }
+    /// Replace the transposition table with an empty one of (at most) `mb` MB.
+    /// Not while a search is running - the search threads hold on to the old table.
+    pub fn set_hash_size(&mut self, mb: usize) {
+        // mb is rounded down to a power of two - compare the resulting table sizes
+        if Transpositions::entries_for_mb(mb) != self.ttable.len() {
+            self.ttable = Arc::new(Transpositions::with_capacity_mb(mb));
         }
+    }
 
-        self.board.update(m);
-        let in_check = self.in_check(self.turn());
-        if self.legal_moves().is_empty() && in_check {
-            label.push('#')
-        } else if self.in_check(self.turn()) {
-            label.push('+')
-        }
-        self.board.backdate(m);
-        label
+    // set up a new position - keeps the transposition table (entries are keyed by position)
+    pub fn set_board(&mut self, board: Board) {
+        self.end_game = board.is_end_game();
+        self.board = board;
+        self.history.clear();
+        self.undone.clear();
+    }
+
+    /// SAN label of the legal move m - moves are the legal moves of the position
+    pub fn move2label(&mut self, m: &Move, moves: &[Move]) -> String {
+        san_label(&mut self.board, m, moves)
+    }
+
+    /// The legal move written as `s` in SAN - sloppy input like "e8q" or "ng1f3" is accepted
+    pub fn parse_san(&self, s: &str) -> Result<Move, SanError> {
+        let moves = self.board.legal_moves(self.end_game);
+        san::parse(&self.board, &moves, s)
     }
 
     fn is_quiescent(&self, last: &Move) -> bool {
@@ -163,29 +306,75 @@ impl Game {
     }
 
     pub fn make_move(&mut self, m: Move) {
-        if m.en_passant()
-            || self.board[m.to() as usize] != EMPTY
-            || self.board[m.frm() as usize].kind() == PAWN
-        {
-            self.board.rep.clear(); // ireversible move
-            self.board.half_move_clock = 0;
-        }
-        self.ttable.clear();
+        self.undone.clear();
+        self.apply_move(m);
+    }
+
+    fn apply_move(&mut self, m: Move) {
         self.board.update(&m);
 
         //adjust king value in end game
         self.end_game = self.board.is_end_game();
         self.board.full_move_count += 1;
+        self.history.push(m);
+    }
 
-        //update castling permissions
-        match (self.board[m.to() as usize], m.frm()) {
-            (WKING, 24) => self.board.can_castle &= !CASTLE_W_SHORT & !CASTLE_W_LONG,
-            (BKING, 31) => self.board.can_castle &= !CASTLE_B_SHORT & !CASTLE_B_LONG,
-            (WROOK, 0) => self.board.can_castle &= !CASTLE_W_SHORT,
-            (WROOK, 56) => self.board.can_castle &= !CASTLE_W_LONG,
-            (BROOK, 7) => self.board.can_castle &= !CASTLE_B_SHORT,
-            (BROOK, 63) => self.board.can_castle &= !CASTLE_B_LONG,
-            _ => (),
+    /// Take back the last move - castling rights, half-move clock and repetitions
+    /// are restored with it. None at the start of the game
+    pub fn undo_move(&mut self) -> Option<Move> {
+        let m = self.history.pop()?;
+        self.board.backdate(&m);
+        self.end_game = self.board.is_end_game();
+        self.board.full_move_count -= 1;
+        self.undone.push(m);
+        Some(m)
+    }
+
+    /// Play the last move taken back again - None if there is none, or a new
+    /// move has been made since
+    pub fn redo_move(&mut self) -> Option<Move> {
+        let m = self.undone.pop()?;
+        self.apply_move(m);
+        Some(m)
+    }
+
+    /// Moves played since the position was set up - with their SAN labels, worked out
+    /// by replaying the moves from the start position
+    pub fn history(&self) -> Vec<(Move, String)> {
+        let mut board = self.board.clone();
+        for m in self.history.iter().rev() {
+            board.backdate(m);
+        }
+        self.history
+            .iter()
+            .map(|m| {
+                let moves = board.legal_moves(false);
+                let label = san_label(&mut board, m, &moves);
+                board.update(m);
+                (*m, label)
+            })
+            .collect()
+    }
+
+    /// Result if the game is over - mate and stalemate first, then the draws by rule
+    pub fn outcome(&self) -> Option<GameResult> {
+        if self.board.legal_moves(self.end_game).is_empty() {
+            return Some(if self.in_check(self.turn()) {
+                GameResult::Checkmate {
+                    winner: self.turn().opposite(),
+                }
+            } else {
+                GameResult::Draw(DrawReason::Stalemate)
+            });
+        }
+        if self.board.insufficient_material() {
+            Some(GameResult::Draw(DrawReason::InsufficientMaterial))
+        } else if self.board.rep_count() >= 3 {
+            Some(GameResult::Draw(DrawReason::Repetition))
+        } else if self.board.half_moves() >= 100 {
+            Some(GameResult::Draw(DrawReason::FiftyMoves))
+        } else {
+            None
         }
     }
 
@@ -194,30 +383,13 @@ impl Game {
         self.board.in_check(colour)
     }
 
-    fn legal_move(&mut self, m: &Move) -> bool {
-        // verify move does not expose own king
-        self.board.update(m);
-        let flag = self.board.in_check(self.board.colour.opposite());
-        self.board.backdate(m);
-        !flag
+    pub fn legal_moves(&mut self) -> MoveList {
+        self.moves()
     }
 
-    pub fn legal_moves(&mut self) -> Vec<Move> {
-        let in_check = self.in_check(self.board.colour);
-        let mut moves = self.moves(in_check);
-        moves.retain(|m| self.legal_move(m));
-        moves
-    }
-
-    fn moves(&mut self, in_check: bool) -> Vec<Move> {
-        let mut l = self.board.moves(in_check, self.end_game);
-        if self.board.colour.is_white() {
-            //l.sort_by(|b, a| a.val.cmp(&b.val)); // decreasing
-            l.sort_unstable_by(|b, a| a.val.cmp(&b.val)); // decreasing
-        } else {
-            //l.sort_by(|a, b| a.val.cmp(&b.val)); // increasing
-            l.sort_unstable_by(|a, b| a.val.cmp(&b.val)); // increasing
-        }
+    fn moves(&mut self) -> MoveList {
+        let mut l = self.board.legal_moves(self.end_game);
+        sort_by_val(&mut l, self.board.colour);
         self.n_searched += l.len();
         l
     }
@@ -226,39 +398,51 @@ impl Game {
         self.board.colour
     }
 
+    // poll stop flag, node & time limits - true if the search should be broken off
+    #[inline]
+    fn stopped(&mut self) -> bool {
+        if !self.aborted {
+            self.n_polls = self.n_polls.wrapping_add(1);
+            self.aborted = self.stop.load(Ordering::Relaxed)
+                || self.n_searched > self.max_nodes
+                || (self.n_polls.is_multiple_of(1024)
+                    && self.deadline.is_some_and(|d| Instant::now() >= d));
+        }
+        self.aborted
+    }
+
     fn quiescence_fab(&mut self, alp: i16, beta: i16, last: &Move, rfab: bool) -> i16 {
-        let colour = self.board.colour;
+        if self.stopped() {
+            return 0;
+        }
 
         let mut bscore = None;
         let mut alpha = alp;
-        let in_check = false; // TODO - calculate?
-        let mut moves = self.moves(in_check);
+        let mut moves = self.board.captures(self.end_game);
+        self.n_searched += moves.len();
         moves.retain(|m|
-            //let ic = self.in_check(colour);
+            // promotions without a capture are left to the regular search
             if rfab {
                 m.to() == last.to()
             } else {
                 m.en_passant() || self.board[m.to() as usize] != EMPTY
             }
         );
-        for m in moves {
-            self.board.update(&m);
-            if !self.in_check(colour) {
-                // legal move
-                let score = -self.quiescence_fab(-beta, -alpha, &m, true);
-                match bscore {
-                    Some(bs) if score <= bs => (),
-                    _ => {
-                        if score >= beta {
-                            self.board.backdate(&m);
-                            return score;
-                        }
-                        bscore = Some(score);
-                        alpha = max(alpha, score);
+        sort_by_val(&mut moves, self.board.colour);
+        for m in &moves {
+            self.board.update(m);
+            let score = -self.quiescence_fab(-beta, -alpha, m, true);
+            self.board.backdate(m);
+            match bscore {
+                Some(bs) if score <= bs => (),
+                _ => {
+                    if score >= beta {
+                        return score;
                     }
+                    bscore = Some(score);
+                    alpha = max(alpha, score);
                 }
             }
-            self.board.backdate(&m);
         }
         if let Some(bs) = bscore {
             bs
@@ -267,11 +451,51 @@ impl Game {
         }
     } // fn quiescence fab
 
+    // new best move at ply - prepend it to the line found at ply+1
+    fn update_pv(&mut self, ply: usize, m: &Move) {
+        let (head, tail) = self.pv.split_at_mut(ply + 1);
+        head[ply].clear();
+        head[ply].push(*m);
+        head[ply].extend_from_slice(&tail[0]);
+    }
+
+    // continue the pv with moves from the transposition table - the pv table
+    // comes up short when a line is cut off by a transposition table hit
+    fn extend_pv(&mut self, pv: &mut Vec<Move>) {
+        for m in pv.iter() {
+            self.board.update(m);
+        }
+        while pv.len() < MAX_PLY && !self.board.is_repetition() {
+            let Some(e) = self.ttable.probe(self.board.hash) else {
+                break;
+            };
+            let best = e.best_move();
+            let Some(m) = self
+                .board
+                .legal_moves(self.end_game)
+                .into_iter()
+                .find(|m| m.data == best.data)
+            else {
+                break;
+            };
+            self.board.update(&m);
+            pv.push(m);
+        }
+        for m in pv.iter().rev() {
+            self.board.backdate(m);
+        }
+    }
+
     pub fn pvs(&mut self, depth: u16, ply: usize, alpha: i16, beta: i16, last: &Move) -> i16 {
-        if let Some(count) = self.board.rep.get(&self.board.hash) {
-            if *count >= 2 {
-                return 0;
-            }
+        if self.stopped() {
+            return 0;
+        }
+        self.pv[ply].clear();
+        if ply >= MAX_PLY {
+            return self.board.eval();
+        }
+        if self.board.is_repetition() || self.board.insufficient_material() {
+            return 0;
         }
 
         let mut alpha = alpha;
@@ -282,18 +506,19 @@ impl Game {
 
         let kmove = if let Some(e) = self.ttable.probe(self.board.hash) {
             if e.depth() >= depth {
+                let score = e.score(ply);
                 if e.exact_bound() {
-                    return e.score();
+                    return score;
                 } else if e.lower_bound() {
-                    alpha = max(alpha, e.score())
+                    alpha = max(alpha, score)
                 } else {
-                    beta = min(beta, e.score())
+                    beta = min(beta, score)
                 }
                 if alpha >= beta {
-                    return e.score();
+                    return score;
                 }
             }
-            Some(e.frmto())
+            Some(e.best_move())
         } else {
             None
         };
@@ -308,46 +533,97 @@ impl Game {
             (_, false) => depth,
         };
 
-        let mut moves = self.moves(in_check);
-        if let Some(k) = kmove {
-            move_to_head(&mut moves, &k);
+        // null move pruning - if passing the turn still fails high, a real move will too.
+        // Not in pv nodes, and not without pieces (pawn & king endings) where zugzwang is common
+        if !in_check
+            && depth >= 3
+            && !last.is_null()
+            && beta - alpha == 1
+            && beta.abs() < INFINITE - 1000
+            && self.board.has_non_pawn_material(colour)
+            && self.board.eval() >= beta
+        {
+            let r = if depth > 6 { 3 } else { 2 };
+            self.board.null_update();
+            let score = -self.pvs(depth - 1 - r, ply + 1, -beta, -beta + 1, &NULL_MOVE);
+            self.board.null_backdate();
+            if self.aborted {
+                return 0;
+            }
+            if score >= beta {
+                return score;
+            }
         }
-        for m in moves.iter() {
-            self.board.update(m);
-            if !self.in_check(colour) {
-                // legal move
-                if bmove.is_none() {
-                    bscore = -self.pvs(depth - 1, ply + 1, -beta, -alpha, m); // full beam
-                    bmove = Some(m);
+
+        let mut picker = MovePicker::staged(
+            &self.board,
+            self.end_game,
+            kmove,
+            &self.heuristics,
+            ply,
+            last,
+        );
+        while let Some(m) = picker.next_move(&self.board, &self.heuristics) {
+            let quiet = is_quiet(&self.board, &m);
+            self.board.update(&m);
+            if bmove.is_none() {
+                bscore = -self.pvs(depth - 1, ply + 1, -beta, -alpha, &m); // full beam
+                bmove = Some(m);
+                self.update_pv(ply, &m);
+            } else {
+                // late move reductions - quiet moves ranked low by the move picker
+                // are searched shallower first, and again at full depth if they surprise
+                let r = if depth >= 3
+                    && !in_check
+                    && picker.n_picked() > 3
+                    && picker.last_was_late()
+                    && !self.in_check(self.board.colour)
+                {
+                    if depth > 5 && picker.n_picked() > 8 {
+                        2
+                    } else {
+                        1
+                    }
                 } else {
-                    let mut score = -self.pvs(
-                        depth - 1,
-                        ply + 1,
-                        -max(alpha, bscore) - 1,
-                        -max(alpha, bscore),
-                        m,
-                    );
-                    if score > bscore {
-                        if score > max(bscore, alpha) && score < beta && depth > 2 {
-                            score = -self.pvs(depth - 1, ply + 1, -beta, -score, m);
-                        }
-                        bscore = score;
-                        bmove = Some(m);
+                    0
+                };
+                let a = max(alpha, bscore);
+                let mut score = -self.pvs(depth - 1 - r, ply + 1, -a - 1, -a, &m);
+                if r > 0 && score > a {
+                    score = -self.pvs(depth - 1, ply + 1, -a - 1, -a, &m);
+                }
+                if score > bscore {
+                    if score > a && score < beta && depth > 2 {
+                        score = -self.pvs(depth - 1, ply + 1, -beta, -score, &m);
                     }
+                    bscore = score;
+                    bmove = Some(m);
+                    self.update_pv(ply, &m);
                 }
             }
-            self.board.backdate(m);
+            self.board.backdate(&m);
+            if self.aborted {
+                break;
+            }
             if bscore >= beta {
+                if quiet {
+                    self.heuristics.cutoff(colour, ply, depth, last, &m);
+                }
                 break;
             }
         }
+        self.n_searched += picker.n_generated();
+        if self.aborted {
+            return 0; // incomplete - do not store in the transposition table
+        }
 
         match (bmove, in_check) {
             (None, false) => 0,
             (None, true) => bscore,
             (Some(m), _) => {
+                let bound = Bound::new(bscore, alpha, beta);
                 self.ttable
-                    .store(self.board.hash, depth, bscore, alpha, beta, m);
+                    .store(self.board.hash, depth, ply, bscore, bound, &m);
                 bscore
             }
         }
@@ -357,55 +633,246 @@ impl Game {
         &mut self,
         moves: &[Move],
         max_searched: usize,
+        max_depth: u16,
         verbose: bool,
-    ) -> Vec<(Move, i16)> {
+    ) -> SearchResult {
+        // max_searched is a soft threshold - checked between iterations
+        let limits = SearchLimits::depth(max_depth);
+        self.search(moves, &limits, |res| {
+            if verbose {
+                let (bmove, bscore) = res.moves[0];
+                println!(
+                    "Depth {:>2} #searched {:>8} #researches {:>2} bmove: {} bscore: {} pv: {}",
+                    res.depth,
+                    res.n_searched,
+                    res.researches,
+                    bmove,
+                    bscore,
+                    pv2str(res.pv())
+                );
+                for (i, pv) in res.pvs.iter().enumerate().skip(1) {
+                    println!(
+                        "{:>53} {}: {} pv: {}",
+                        "",
+                        i + 1,
+                        res.moves[i].1,
+                        pv2str(pv)
+                    );
+                }
+            }
+            res.n_searched <= max_searched
+        })
+    }
+
+    /// Iterative deepening search within `limits`, or until the stop flag is raised -
+    /// at most MAX_PLY iterations.
+    /// Calls `report` after each completed iteration - returning false from `report`
+    /// breaks off the search.
+    /// Returns the root moves & pvs from the last completed iteration.
+    /// Note that the stop flag is not cleared - that is up to the caller.
+    ///
+    /// With self.threads > 1 the search is lazy smp: helper threads search the same
+    /// root and share their findings via the transposition table, while the result
+    /// is taken from this (the main) thread.
+    pub fn search<F>(&mut self, moves: &[Move], limits: &SearchLimits, report: F) -> SearchResult
+    where
+        F: FnMut(&SearchResult) -> bool,
+    {
+        self.ttable.new_search();
+        if self.threads <= 1 || moves.len() <= 1 {
+            return self.search_thread(moves, limits, 2, report);
+        }
+
+        let helpers_stop = Arc::new(AtomicBool::new(false));
+        let mut helpers: Vec<Game> = (1..self.threads)
+            .map(|_| self.helper(Arc::clone(&helpers_stop)))
+            .collect();
+        thread::scope(|s| {
+            let handles: Vec<_> = helpers
+                .iter_mut()
+                .enumerate()
+                .map(|(i, helper)| {
+                    // half the helpers are one iteration ahead - different depths
+                    // spread the work better than identical searches
+                    let first_depth = 2 + (i % 2) as u16;
+                    s.spawn(move || {
+                        helper
+                            .search_thread(moves, &SearchLimits::default(), first_depth, |_| true);
+                        helper.n_searched
+                    })
+                })
+                .collect();
+            let mut res = self.search_thread(moves, limits, 2, report);
+            helpers_stop.store(true, Ordering::Relaxed);
+            let n_helpers: usize = handles
+                .into_iter()
+                .map(|h| h.join().expect("search thread panicked"))
+                .sum();
+            self.n_searched += n_helpers;
+            res.n_searched += n_helpers;
+            res
+        })
+    }
+
+    fn search_thread<F>(
+        &mut self,
+        moves: &[Move],
+        limits: &SearchLimits,
+        first_depth: u16,
+        mut report: F,
+    ) -> SearchResult
+    where
+        F: FnMut(&SearchResult) -> bool,
+    {
         // top level pvs - does iterative deepening, sorts moves
-        // note that only the best move has exact scoring...
+        // note that only the best self.multipv moves have exact scoring...
 
         if moves.is_empty() {
-            return vec![];
+            return SearchResult::default();
         }
 
+        let start = Instant::now();
+        let budget = limits.time_budget(self.turn());
+        self.deadline = budget.map(|(_, maximum)| start + maximum);
+        self.max_nodes = limits.nodes.unwrap_or(usize::MAX);
+        self.aborted = false;
         self.n_searched = 0;
-        let mut pq0: Vec<(Move, i16)> = moves.iter().map(|m| (*m, 0)).collect();
-        for depth in (2..).step_by(1) {
-            let mut pq: Vec<(Move, i16)> = Vec::new();
-            let mut alpha = -INFINITE;
-            let beta = INFINITE;
-            let mut bscore = alpha;
-
-            for (i, (m, _v)) in pq0.iter().enumerate() {
-                self.board.update(m);
-                alpha = max(bscore, alpha);
-                let mut score = if i == 0 {
-                    -self.pvs(depth - 1, 1, -beta, -alpha, m) // full beam
-                } else {
-                    -self.pvs(depth - 1, 1, -alpha - 1, -alpha, m)
-                };
-
-                if score > bscore {
-                    if score > alpha && score < beta && depth > 2 {
-                        score = -self.pvs(depth - 1, 1, -beta, -score, m);
-                    }
-                    bscore = score;
+        self.heuristics.age();
+        let n_pv = self.multipv.clamp(1, moves.len());
+        let mut res = SearchResult {
+            moves: moves.iter().map(|m| (*m, 0)).collect(),
+            pvs: vec![vec![moves[0]]],
+            depth: 0,
+            n_searched: 0,
+            researches: 0,
+        };
+        let mut researches = 0;
+        // every root move reaches a dead draw - deeper searches can not change the scores
+        let dead_draw = moves.iter().all(|m| {
+            self.board.update(m);
+            let draw = self.board.insufficient_material();
+            self.board.backdate(m);
+            draw
+        });
+        // pvs keeps a pv and heuristics for at most MAX_PLY plies
+        for depth in first_depth..=MAX_PLY as u16 {
+            // aspiration window from the previous n_pv-th score to the best score - widened
+            // on fail low / high
+            let (best, nth) = (res.moves[0].1, res.moves[n_pv - 1].1);
+            let mut delta = ASPIRATION_DELTA;
+            let (mut lo, mut hi) = if depth > first_depth
+                && best.abs() < INFINITE - 1000
+                && nth.abs() < INFINITE - 1000
+            {
+                (nth - delta, best + delta)
+            } else {
+                (-INFINITE, INFINITE)
+            };
+            let mut order = res.moves.clone();
+            let (lines, mut rest) = loop {
+                let (lines, mut rest) = self.search_root(&order, depth, n_pv, lo, hi);
+                if self.aborted {
+                    break (lines, rest);
+                }
+                let fail_low = lines.len() < n_pv && lo > -INFINITE;
+                let fail_high = lines.first().is_some_and(|(s, _)| *s >= hi) && hi < INFINITE;
+                if !fail_low && !fail_high {
+                    break (lines, rest);
                 }
-                self.board.backdate(m);
-                pq.push((*m, score));
+                researches += 1;
+                // a mate score will not fit in a window - open it fully
+                let mate = lines.first().is_some_and(|(s, _)| *s >= INFINITE - 1000);
+                if fail_low {
+                    lo = lo.saturating_sub(delta).max(-INFINITE);
+                }
+                if fail_high {
+                    hi = if mate {
+                        INFINITE
+                    } else {
+                        hi.saturating_add(delta).min(INFINITE)
+                    };
+                }
+                delta = delta.saturating_mul(2);
+                rest.sort_by_key(|&(_, score)| Reverse(score));
+                order = lines.iter().map(|(s, pv)| (pv[0], *s)).collect();
+                order.extend(rest);
+            };
+            if self.aborted {
+                break; // keep the result of the last completed iteration
             }
-            pq.sort_by(|b, a| a.1.cmp(&b.1)); // decreasing
-            pq0 = pq;
-            if verbose {
-                println!(
-                    "Depth {:>2} #searched {:>8} bmove: {} bscore: {}",
-                    depth, self.n_searched, pq0[0].0, bscore
-                );
+            rest.sort_by_key(|&(_, score)| Reverse(score)); // decreasing
+            let mut pq: Vec<(Move, i16)> = lines.iter().map(|(s, pv)| (pv[0], *s)).collect();
+            pq.extend(rest);
+            let mut pvs: Vec<Vec<Move>> = lines.into_iter().take(n_pv).map(|(_, pv)| pv).collect();
+            for pv in pvs.iter_mut() {
+                self.extend_pv(pv);
+            }
+            res = SearchResult {
+                moves: pq,
+                pvs,
+                depth,
+                n_searched: self.n_searched,
+                researches,
+            };
+            if !report(&res) {
+                break;
             }
-            if self.n_searched > max_searched || pq0[0].1.abs() >= INFINITE - 1000 {
+            if limits.depth.is_some_and(|d| depth >= d)
+                || budget.is_some_and(|(optimum, _)| start.elapsed() * 2 > optimum)
+                || res.moves[0].1.abs() >= INFINITE - 1000
+                || dead_draw
+            {
                 break;
             }
         }
-        pq0
+        self.deadline = None;
+        self.max_nodes = usize::MAX;
+        res
     } // fn score_moves
+
+    // one pass over the root moves within the window (lo, hi).
+    // Returns the lines that beat lo - best first, at least n_pv of them unless the
+    // search failed low - and the remaining moves with their (upper bound) scores
+    fn search_root(
+        &mut self,
+        moves: &[(Move, i16)],
+        depth: u16,
+        n_pv: usize,
+        lo: i16,
+        hi: i16,
+    ) -> RootLines {
+        let mut lines: Vec<(i16, Vec<Move>)> = Vec::new(); // exact scores, best first
+        let mut rest: Vec<(Move, i16)> = Vec::new();
+        let beta = hi;
+
+        for (m, _v) in moves.iter() {
+            // the move must beat the n_pv'th best line to be scored exactly
+            let full = lines.len() < n_pv;
+            let alpha = if full { lo } else { max(lo, lines[n_pv - 1].0) };
+            self.board.update(m);
+            let mut score = if full {
+                -self.pvs(depth - 1, 1, -beta, -alpha, m) // full beam
+            } else {
+                -self.pvs(depth - 1, 1, -alpha - 1, -alpha, m)
+            };
+            if !full && score > alpha && score < beta {
+                score = -self.pvs(depth - 1, 1, -beta, -alpha, m);
+            }
+            if score > alpha {
+                let mut pv = vec![*m];
+                pv.extend_from_slice(&self.pv[1]);
+                let q = lines.partition_point(|(s, _)| *s >= score);
+                lines.insert(q, (score, pv));
+            } else {
+                rest.push((*m, score));
+            }
+            self.board.backdate(m);
+            if self.aborted {
+                break;
+            }
+        }
+        (lines, rest)
+    }
 }
 
 #[cfg(test)]
@@ -420,4 +887,220 @@ mod tests {
             "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
         )
     }
+
+    #[test]
+    fn test_outcome() {
+        let outcome = |fen: &str| Game::new(Board::from_fen(fen).unwrap()).outcome();
+        assert_eq!(outcome(ROOT_FEN), None);
+        let mate = "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3";
+        assert_eq!(outcome(mate), Some(GameResult::Checkmate { winner: BLACK }));
+        assert_eq!(outcome(mate).unwrap().to_string(), "0-1 Checkmate");
+        let stalemate = outcome("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1");
+        assert_eq!(stalemate, Some(GameResult::Draw(DrawReason::Stalemate)));
+        assert_eq!(
+            outcome("4k3/8/8/8/8/8/4P3/4K3 w - - 100 80"),
+            Some(GameResult::Draw(DrawReason::FiftyMoves))
+        );
+        for (fen, dead) in [
+            ("4k3/8/8/8/8/8/8/4K3 w - - 0 1", true),
+            ("4k3/8/8/8/8/8/8/4KN2 w - - 0 1", true),
+            ("4kb2/8/8/8/8/8/8/2B1K3 w - - 0 1", true), // bishops on the same colour
+            ("4k1b1/8/8/8/8/8/8/2B1K3 w - - 0 1", false),
+            ("4kn2/8/8/8/8/8/8/4KN2 w - - 0 1", false),
+            ("4k3/8/8/8/8/8/8/4KR2 w - - 0 1", false),
+        ] {
+            let expected = dead.then_some(GameResult::Draw(DrawReason::InsufficientMaterial));
+            assert_eq!(outcome(fen), expected, "{fen}");
+        }
+
+        let mut game = Game::new(Board::from_fen("4k3/8/8/8/8/8/4P3/4K1N1 w - - 0 1").unwrap());
+        for _ in 0..2 {
+            for s in ["g1f3", "e8d8", "f3g1", "d8e8"] {
+                assert_eq!(game.outcome(), None);
+                let m = game.legal_moves().into_iter().find(|m| m.to_string() == s);
+                game.make_move(m.unwrap());
+            }
+        }
+        assert_eq!(
+            game.outcome(),
+            Some(GameResult::Draw(DrawReason::Repetition))
+        );
+
+        // taking the rook leaves a knight up - which the search should score as a draw
+        let mut game = Game::new(Board::from_fen("4k3/8/8/8/8/8/5r2/3NK3 w - - 0 1").unwrap());
+        let moves = game.legal_moves();
+        let (m, score) = game
+            .search(&moves, &SearchLimits::depth(4), |_| true)
+            .best()
+            .unwrap();
+        assert_eq!((I2SQ[m.to() as usize], score), ("f2", 0));
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let mut game = Game::default();
+        let start = (game.board.to_fen(), game.board.hash);
+        let play = |game: &mut Game, moves: &str| {
+            for s in moves.split(' ') {
+                let m = game.legal_moves().into_iter().find(|m| m.to_string() == s);
+                game.make_move(m.unwrap());
+            }
+        };
+        play(
+            &mut game,
+            "e2e4 e7e5 g1f3 b8c6 f1c4 g8f6 e1g1 f6e4 d1e2 e4f6 e2d1 f6e4",
+        );
+        let history = game.history();
+        let labels: Vec<&str> = history.iter().map(|(_, l)| l.as_str()).collect();
+        assert_eq!(
+            labels[..8],
+            ["e4", "e5", "Nf3", "Nc6", "Bc4", "Nf6", "O-O", "Nxe4"]
+        );
+        let end = (game.board.to_fen(), game.board.hash);
+        assert_eq!(game.board.rep_count(), 2);
+
+        assert!(game.undo_move().is_some());
+        assert_eq!(game.board.rep_count(), 1);
+        assert_eq!(game.board.half_moves(), 3);
+        while game.undo_move().is_some() {}
+        assert_eq!((game.board.to_fen(), game.board.hash), start);
+        assert!(game.history().is_empty());
+
+        while game.redo_move().is_some() {}
+        assert_eq!((game.board.to_fen(), game.board.hash), end);
+        assert_eq!(game.board.rep_count(), 2);
+
+        // a new move drops the moves taken back
+        game.undo_move();
+        play(&mut game, "d7d6");
+        assert_eq!(game.redo_move(), None);
+        assert_eq!(game.history().last().unwrap().1, "d6");
+    }
+
+    #[test]
+    fn test_search_limits() {
+        let mut game = Game::default();
+        let moves = game.legal_moves();
+
+        let mut last_depth = 0;
+        let res = game.search(&moves, &SearchLimits::depth(4), |res| {
+            last_depth = res.depth;
+            true
+        });
+        assert_eq!(res.moves.len(), moves.len());
+        assert_eq!((last_depth, res.depth), (4, 4));
+
+        // node limit is enforced mid-iteration
+        let res = game.search(&moves, &SearchLimits::nodes(10_000), |_| true);
+        assert_eq!(res.moves.len(), moves.len());
+        assert!(game.n_searched < 20_000);
+
+        // raised stop flag breaks off at once
+        game.stop.store(true, Ordering::SeqCst);
+        let res = game.search(&moves, &SearchLimits::default(), |_| true);
+        assert_eq!(res.moves.len(), moves.len());
+        assert!(game.n_searched < 1000);
+    }
+
+    #[test]
+    fn test_dead_draw() {
+        // bare kings - one iteration settles it, with or without a time limit
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        for limits in [SearchLimits::movetime(2000), SearchLimits::default()] {
+            let mut game = Game::new(board.clone());
+            let moves = game.legal_moves();
+            let mut iterations = 0;
+            let res = game.search(&moves, &limits, |_| {
+                iterations += 1;
+                true
+            });
+            assert_eq!((iterations, res.depth, res.moves[0].1), (1, 2, 0));
+        }
+    }
+
+    #[test]
+    fn test_pv() {
+        let mut game = Game::default();
+        let fen0 = game.board.to_fen();
+        let moves = game.legal_moves();
+        let res = game.search(&moves, &SearchLimits::depth(5), |_| true);
+        assert_eq!(game.board.to_fen(), fen0);
+        assert_eq!(res.pv()[0], res.moves[0].0);
+        assert!(res.pv().len() >= 2);
+
+        // pv must be a sequence of legal moves
+        for m in res.pv() {
+            let legal = game.legal_moves();
+            assert!(
+                legal.contains(m),
+                "{m} not legal in pv {}",
+                pv2str(res.pv())
+            );
+            game.make_move(*m);
+        }
+    }
+
+    #[test]
+    fn test_threads() {
+        let mut game = Game::new(Board::default());
+        game.threads = 4;
+        let moves = game.legal_moves();
+        let res = game.search(&moves, &SearchLimits::depth(5), |_| true);
+        assert_eq!(res.depth, 5);
+        assert_eq!(res.moves.len(), moves.len());
+        assert_eq!(game.board.to_fen(), Board::default().to_fen());
+
+        game.make_move(res.moves[0].0);
+        let moves = game.legal_moves();
+        let res = game.search(&moves, &SearchLimits::nodes(100_000), |_| true);
+        assert!(res.best().is_some());
+    }
+
+    #[test]
+    fn test_multipv() {
+        let board = Board::from_fen("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1").unwrap();
+        let mut game = Game::new(board);
+        game.multipv = 3;
+        let moves = game.legal_moves();
+        let res = game.search(&moves, &SearchLimits::depth(4), |_| true);
+        assert_eq!(res.pvs.len(), 3);
+        assert_eq!(res.moves[0].0.to_string(), "d2d5"); // Rxd5
+        for i in 0..3 {
+            assert_eq!(res.pvs[i][0], res.moves[i].0);
+        }
+        assert!(res.moves[0].1 >= res.moves[1].1 && res.moves[1].1 >= res.moves[2].1);
+    }
+
+    #[test]
+    fn test_parse_san() {
+        // every label move2label writes reads back as the same move
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 b kq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        ] {
+            let mut game = Game::new(Board::from_fen(fen).unwrap());
+            let moves = game.legal_moves();
+            for m in &moves {
+                let label = game.move2label(m, &moves);
+                assert_eq!(game.parse_san(&label), Ok(*m), "{fen} {label}");
+            }
+        }
+        let game = Game::default();
+        assert_eq!(
+            game.parse_san("Ke2"),
+            Err(SanError::Illegal("Ke2".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_set_hash_size() {
+        // GUIs resend all options - the same size must not clear the table
+        let mut game = Game::with_hash_size(Board::default(), 100);
+        let table = Arc::clone(&game.ttable);
+        game.set_hash_size(100);
+        assert!(Arc::ptr_eq(&table, &game.ttable));
+        game.set_hash_size(1);
+        assert!(!Arc::ptr_eq(&table, &game.ttable));
+    }
 }
@@ -0,0 +1,99 @@
+use crate::val::Colour;
+use std::time::Duration;
+
+// safety margin (ms) for communication delays when playing on the clock
+const MOVE_OVERHEAD: u64 = 50;
+// assumed number of moves left when the gui does not tell (movestogo)
+const MOVES_TO_GO: u64 = 30;
+
+/// Conditions for breaking off a search - times in ms.
+/// All limits are optional; a search without limits runs until the stop flag is raised
+/// (or a mate is found).
+#[derive(Debug, Clone, Default)]
+pub struct SearchLimits {
+    pub movetime: Option<u64>,
+    pub wtime: Option<u64>,
+    pub btime: Option<u64>,
+    pub winc: Option<u64>,
+    pub binc: Option<u64>,
+    pub movestogo: Option<u64>,
+    pub depth: Option<u16>,
+    pub nodes: Option<usize>,
+    pub infinite: bool,
+}
+
+impl SearchLimits {
+    pub fn nodes(n: usize) -> Self {
+        SearchLimits {
+            nodes: Some(n),
+            ..Default::default()
+        }
+    }
+
+    pub fn depth(depth: u16) -> Self {
+        SearchLimits {
+            depth: Some(depth),
+            ..Default::default()
+        }
+    }
+
+    pub fn movetime(ms: u64) -> Self {
+        SearchLimits {
+            movetime: Some(ms),
+            ..Default::default()
+        }
+    }
+
+    /// Time allocation for the side to move: (optimum, maximum).
+    /// No new iteration is started after half the optimum time has passed, and
+    /// the search is broken off mid-iteration when the maximum is reached.
+    pub fn time_budget(&self, colour: Colour) -> Option<(Duration, Duration)> {
+        if self.infinite {
+            return None;
+        }
+        if let Some(ms) = self.movetime {
+            let ms = Duration::from_millis(ms);
+            return Some((ms, ms));
+        }
+        let (time, inc) = if colour.is_white() {
+            (self.wtime?, self.winc.unwrap_or(0))
+        } else {
+            (self.btime?, self.binc.unwrap_or(0))
+        };
+        let left = time.saturating_sub(MOVE_OVERHEAD).max(1);
+        let mtg = self.movestogo.unwrap_or(MOVES_TO_GO).clamp(1, MOVES_TO_GO);
+        let optimum = (time / mtg + inc * 3 / 4).min(left);
+        let maximum = (3 * optimum).min(left / 2).max(optimum);
+        Some((
+            Duration::from_millis(optimum),
+            Duration::from_millis(maximum),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::val::{BLACK, WHITE};
+
+    #[test]
+    fn test_time_budget() {
+        assert!(SearchLimits::default().time_budget(WHITE).is_none());
+        assert!(SearchLimits::depth(5).time_budget(WHITE).is_none());
+
+        let (opt, max) = SearchLimits::movetime(1000).time_budget(BLACK).unwrap();
+        assert_eq!((opt.as_millis(), max.as_millis()), (1000, 1000));
+
+        let limits = SearchLimits {
+            wtime: Some(60_000),
+            btime: Some(1_000),
+            ..Default::default()
+        };
+        let (opt, max) = limits.time_budget(WHITE).unwrap();
+        assert_eq!(opt.as_millis(), 2000);
+        assert!(max >= opt && max.as_millis() < 30_000);
+        // never plan to use more than what is left on the clock
+        let (opt, max) = limits.time_budget(BLACK).unwrap();
+        assert!(opt <= max && max.as_millis() < 1_000);
+    }
+}
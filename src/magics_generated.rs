@@ -0,0 +1,134 @@
+// Magic numbers generated by bin/build_magics.rs
+
+pub const ROOK_MAGICS: [u64; 64] = [
+    2341873180771095168,
+    306244912104345600,
+    180161579430445065,
+    1477185144585521152,
+    72071003060109392,
+    2377909416532967426,
+    108087490602075520,
+    144116288128680068,
+    313422421468119168,
+    10973161367158145032,
+    4612249045692350530,
+    2378182215802552576,
+    612771093152794368,
+    2379308056132453376,
+    1125917355671810,
+    154389030211944832,
+    161078522691584,
+    9007474401099808,
+    10088345740874088464,
+    19791779727424,
+    72063091865026816,
+    5188710824491089928,
+    15565711347651074064,
+    74346777255411780,
+    18014538095951936,
+    2305985954319499296,
+    181305088702153088,
+    2252351718031368,
+    5629534028202048,
+    2306405969912923136,
+    720859631560163592,
+    282583078289540,
+    1206965533937762432,
+    13510936325275648,
+    9250394184920076288,
+    144257059511078913,
+    144396697429083664,
+    291608110265663504,
+    162130694754011216,
+    4900233330986844452,
+    2378041479252705288,
+    225180532198244388,
+    576742364787310656,
+    1152930576148332560,
+    38562106170212356,
+    844433587240968,
+    10381078624655704068,
+    6341072675535978513,
+    470766916269572864,
+    45036134787448896,
+    43927689089941632,
+    9223380833216266368,
+    650770163343300992,
+    72198417425760640,
+    9304577576225800320,
+    577587752804286976,
+    45318021077545761,
+    578785678247407650,
+    563226979862562,
+    9511795927740850213,
+    146085547273618435,
+    281492156712961,
+    18015500437553668,
+    1190078555191609922,
+];
+pub const BISHOP_MAGICS: [u64; 64] = [
+    1414136407651518624,
+    10385301857416807424,
+    290772459937071128,
+    1131440423059464,
+    1130444527542352,
+    9511745487498117376,
+    75024179543408704,
+    2920041208466245632,
+    1196303145013381,
+    1155178836741083268,
+    6953949255129236642,
+    9157849812830249,
+    4415771639808,
+    2305984365446365186,
+    4613955968795942913,
+    13835623206171968512,
+    90230459795046660,
+    13510820913217798,
+    1125908499268096,
+    9225623853886082064,
+    9224498093538443560,
+    148759546935902496,
+    4693316513002752000,
+    2412332873449985,
+    22597782556771368,
+    1143492697953922,
+    75446288892035328,
+    2884564360647155744,
+    9152335334875136,
+    9804337488574054532,
+    6809301285077508,
+    18298072526252288,
+    46175382382252032,
+    10130869420764058113,
+    2315980515015983616,
+    2315167418143998464,
+    18159543708287232,
+    10385322812553437312,
+    2324143283499172352,
+    20354709547155968,
+    1729576080732866688,
+    2702724934022750288,
+    294425025266598400,
+    72515128920115208,
+    8800455229504,
+    18023196766765091,
+    9224780528496935171,
+    5630066507776256,
+    721706243836215808,
+    144680339267486736,
+    9227884983807905792,
+    283551989768,
+    6917529611807227908,
+    19144838263930900,
+    585751643144880144,
+    288797741876908292,
+    72340205067124808,
+    9335962354057293856,
+    37084882370891813,
+    316659902712064,
+    6049731977728,
+    18025737547153666,
+    2287141556356096,
+    734121958314090530,
+];
@@ -1,20 +1,43 @@
 use crate::bitmaps::*;
-use crate::hashkeys_generated::WHITE_HASH;
+use crate::hashkeys_generated::{CASTLE_HASH, EN_PASSANT_HASH, WHITE_HASH};
 use crate::misc;
+use crate::movelist::MoveList;
 use crate::val::*;
 use crate::val::{BLACK, BPAWN, Colour, Piece, WHITE, WPAWN};
-use std::collections::hash_map::{Entry, HashMap};
 use std::fmt;
 use std::ops::{Index, IndexMut};
 use std::slice::Iter;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Bitmaps {
     pub pieces: [u64; 2],
     pub pawns: u64,
+    pub knights: u64,
+    pub bishops: u64,
+    pub rooks: u64,
+    pub queens: u64,
     pub kings: u64,
 }
 
+impl Bitmaps {
+    #[inline(always)]
+    const fn kind_mut(&mut self, kind: u8) -> &mut u64 {
+        match kind {
+            PAWN => &mut self.pawns,
+            KNIGHT => &mut self.knights,
+            BISHOP => &mut self.bishops,
+            ROOK => &mut self.rooks,
+            QUEEN => &mut self.queens,
+            _ => &mut self.kings,
+        }
+    }
+
+    #[inline(always)]
+    const fn board(&self) -> u64 {
+        self.pieces[0] | self.pieces[1]
+    }
+}
+
 // bitpacking - 1st 12 bits (6+6) for from/to, remaining 4 bits for castling and
 // pawn transforms & enpassant. Castling, en passant & transform are mutually exclusive.
 const CASTLE_BIT: u16 = 1 << 12;
@@ -27,6 +50,22 @@ pub const CASTLE_B_SHORT: u8 = 0b0100;
 pub const CASTLE_B_LONG: u8 = 0b1000;
 pub const FRM_MASK: u16 = 0b111111;
 pub const TO_MASK: u16 = FRM_MASK << TO_SHIFT;
+// passes the turn - only used by the search (null move pruning)
+pub const NULL_MOVE: Move = Move { data: 0, val: 0 };
+// pawns moving here promote
+const PROMOTION_SQUARES: u64 = 0x8181818181818181;
+// a1 and the squares of its colour
+const DARK_SQUARES: u64 = 0x55aa55aa55aa55aa;
+
+/// Which moves to generate - the search tries captures before generating the quiet moves
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum GenType {
+    All,
+    /// captures, en passant & promotions
+    Captures,
+    /// everything else, castling included
+    Quiets,
+}
 
 const fn pack_data(
     castle: bool,
@@ -102,36 +141,119 @@ impl Move {
     pub fn to(&self) -> u8 {
         ((self.data & TO_MASK) >> TO_SHIFT) as u8
     }
+
+    /// Where the king ends up - castling is stored as king takes rook
+    #[inline]
+    pub fn king_to(&self) -> u8 {
+        if self.castle() {
+            castle_squares(self.frm(), self.to()).0
+        } else {
+            self.to()
+        }
+    }
+
+    #[inline]
+    pub fn is_null(&self) -> bool {
+        self.frm() == self.to()
+    }
+
+    /// The legal move given in UCI long algebraic notation, e.g. "e2e4" or "e7e8q".
+    /// Castling may be written as the king's move (e1g1) or as king takes rook (e1h1)
+    pub fn from_uci(board: &Board, s: &str) -> Result<Move, UciMoveError> {
+        let syntax = || UciMoveError::Syntax(s.to_string());
+        if !matches!(s.len(), 4 | 5) || !s.is_ascii() {
+            return Err(syntax());
+        }
+        let frm = misc::parse_chess_coord(&s[0..2]).ok_or_else(syntax)?;
+        let to = misc::parse_chess_coord(&s[2..4]).ok_or_else(syntax)?;
+        let promote = match s.as_bytes().get(4) {
+            None => None,
+            Some(b'q') => Some(QUEEN),
+            Some(b'r') => Some(ROOK),
+            Some(b'b') => Some(BISHOP),
+            Some(b'n') => Some(KNIGHT),
+            _ => return Err(syntax()),
+        };
+
+        // in Chess960 the king's castling move can coincide with a plain king move -
+        // that one is meant then
+        let moves = board.legal_moves(board.is_end_game());
+        moves
+            .iter()
+            .find(|m| {
+                !m.castle()
+                    && (m.frm(), m.to()) == (frm, to)
+                    && promote == m.transform().then(|| m.promote_kind())
+            })
+            .or_else(|| {
+                moves
+                    .iter()
+                    .find(|m| m.castle() && m.frm() == frm && (m.to() == to || m.king_to() == to))
+            })
+            .copied()
+            .ok_or_else(|| UciMoveError::Illegal(s.to_string()))
+    }
+
+    /// UCI long algebraic notation. Castling is e1g1 - or king takes rook (e1h1) in
+    /// Chess960, where the king may already stand on g1
+    pub fn to_uci(&self, chess960: bool) -> String {
+        let to = if chess960 { self.to() } else { self.king_to() };
+        let mut s = format!("{}{}", I2SQ[self.frm() as usize], I2SQ[to as usize]);
+        if self.transform() {
+            s.push(match self.promote_kind() {
+                ROOK => 'r',
+                KNIGHT => 'n',
+                BISHOP => 'b',
+                _ => 'q',
+            });
+        }
+        s
+    }
 }
 
-pub fn ext_frm(data: u16) -> u8 {
-    (data & FRM_MASK) as u8
+/// Why a move in UCI notation was rejected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UciMoveError {
+    /// Not of the form e2e4 / e7e8q
+    Syntax(String),
+    /// No legal move matches
+    Illegal(String),
 }
 
-pub fn ext_to(data: u16) -> u8 {
-    ((data & TO_MASK) >> TO_SHIFT) as u8
+impl fmt::Display for UciMoveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UciMoveError::Syntax(s) => write!(f, "Invalid move: {s}"),
+            UciMoveError::Illegal(s) => write!(f, "Illegal move: {s}"),
+        }
+    }
 }
 
+impl std::error::Error for UciMoveError {}
+
 impl fmt::Display for Move {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let (frm, to) = (self.frm() as usize, self.to() as usize);
+        let (frm, to) = (self.frm() as usize, self.king_to() as usize);
         write!(f, "{}{}{}", I2SQ[frm], I2SQ[to], self.promote_label())
     }
 }
 
+#[derive(Clone)]
 pub struct Board {
     squares: [Piece; 64],
     pub colour: Colour,
-    pub can_castle: u8, // white short, long, black short, long
+    pub can_castle: u8,     // white short, long, black short, long
+    castle_rooks: [u8; 4],  // rook squares - in the order of the can_castle bits
+    castle_masks: [u8; 64], // castling rights kept when a piece moves from or to a square
     pub material: i16,
     pub hash: u64,
     pub half_move_clock: usize, // since last irreversible move
     pub full_move_count: usize,
-    pub rep: HashMap<u64, u8>,
+    history: Vec<u64>, // hash before each move - for finding repetitions
     en_passant_sq: u8,
     bitmaps: Bitmaps,
     end_game_material: i16,
-    log_bms: Vec<(Bitmaps, Piece, u64, u8, u8)>,
+    log_bms: Vec<(Bitmaps, Piece, u64, u8, u8, usize)>,
 }
 
 impl Default for Board {
@@ -219,48 +341,25 @@ impl fmt::Display for Board {
 }
 
 impl Board {
+    /// Times the current position has occurred - 1 the first time. Only positions
+    /// since the last irreversible move can repeat, so the walk back stops there
     pub fn rep_count(&self) -> u8 {
-        if let Some(count) = self.rep.get(&self.hash) {
-            *count
-        } else {
-            0
-        }
+        1 + self.repetitions().filter(|&&h| h == self.hash).count() as u8
     }
 
-    fn rep_inc(&mut self) {
-        //*self.rep.entry(self.hash).or_default() += 1;
-        self.rep
-            .entry(self.hash)
-            .and_modify(|x| *x += 1)
-            .or_insert(1);
+    /// Current position occurred before
+    pub fn is_repetition(&self) -> bool {
+        self.repetitions().any(|&h| h == self.hash)
     }
 
-    fn rep_dec(&mut self) {
-        if let Entry::Occupied(entry) = self
-            .rep
-            .entry(self.hash)
-            .and_modify(|x| *x = x.saturating_sub(1))
-        {
-            if *entry.get() == 0 {
-                self.rep.remove(&self.hash);
-            }
-        }
-
-        // self.rep
-        //     .entry(self.hash)
-        //     .and_modify(|x| *x = x.saturating_sub(1));
-        // if let Some(0) = self.rep.get(&self.hash) {
-        //     self.rep.remove(&self.hash);
-        // }
-
-        // self.rep
-        //     .entry(self.hash)
-        //     .and_modify(|x| *x = if *x == 0 { 0 } else { *x - 1 });
-        // if let Some(count) = self.rep.get(&self.hash) {
-        //     if *count == 0 {
-        //         self.rep.remove(&self.hash);
-        //     }
-        // }
+    // earlier positions with the same side to move
+    fn repetitions(&self) -> impl Iterator<Item = &u64> {
+        let n = self.half_move_clock.min(self.history.len());
+        self.history[self.history.len() - n..]
+            .iter()
+            .rev()
+            .skip(1)
+            .step_by(2)
     }
 
     pub fn from_fen(s: &str) -> Result<Board, String> {
@@ -277,17 +376,44 @@ impl Board {
             Colour::white()
         };
 
+        // KQkq is the outermost rook (X-FEN), a file letter names the rook (Shredder-FEN) -
+        // both needed for Chess960
         let mut can_castle = 0;
+        let mut castle_rooks = [0, 56, 7, 63];
+        let mut castle_masks = [0xFF; 64];
         if parts.len() > 2 {
-            for (c, q) in ['K', 'Q', 'k', 'q'].into_iter().zip([
-                CASTLE_W_SHORT,
-                CASTLE_W_LONG,
-                CASTLE_B_SHORT,
-                CASTLE_B_LONG,
-            ]) {
-                if parts[2].contains(c) {
-                    can_castle |= q
-                }
+            for c in parts[2].chars() {
+                let (king, rook, rank) = if c.is_ascii_uppercase() {
+                    (WKING, WROOK, 0)
+                } else {
+                    (BKING, BROOK, 7)
+                };
+                let back_rank = |i: usize| i * 8 + rank;
+                let Some(ksq) = (0..8).map(back_rank).find(|&i| squares[i] == king) else {
+                    continue;
+                };
+                let rsq = match c.to_ascii_lowercase() {
+                    'k' => (0..8)
+                        .map(back_rank)
+                        .take_while(|&i| i < ksq)
+                        .find(|&i| squares[i] == rook),
+                    'q' => (0..8)
+                        .rev()
+                        .map(back_rank)
+                        .take_while(|&i| i > ksq)
+                        .find(|&i| squares[i] == rook),
+                    f @ 'a'..='h' => Some(back_rank(7 - (f as u8 - b'a') as usize))
+                        .filter(|&i| i != ksq && squares[i] == rook),
+                    _ => None,
+                };
+                let Some(rsq) = rsq else {
+                    continue;
+                };
+                let i = 2 * rank / 7 + (rsq > ksq) as usize;
+                can_castle |= 1 << i;
+                castle_rooks[i] = rsq as u8;
+                castle_masks[rsq] &= !(1 << i);
+                castle_masks[ksq] &= !(1 << i);
             }
         }
 
@@ -325,15 +451,16 @@ impl Board {
         let bitmaps = to_bitmaps(&squares);
 
         let end_game_material = abs_material(&from_fen(ROOT_FEN)) / 3;
-        let hash = calc_hash(&squares, colour);
+        let hash = calc_hash(&squares, colour, can_castle, en_passant_sq);
         let material = material(&squares);
-        let rep = HashMap::from([(hash, 1)]);
 
         Ok(Board {
             squares,
             bitmaps,
             colour,
             can_castle,
+            castle_rooks,
+            castle_masks,
             end_game_material,
             log_bms: vec![],
             material,
@@ -341,7 +468,7 @@ impl Board {
             en_passant_sq,
             half_move_clock,
             full_move_count,
-            rep,
+            history: vec![],
         })
     }
 
@@ -372,12 +499,30 @@ impl Board {
         s.push_str(if self.colour.is_white() { " w" } else { " b" });
         s.push(' ');
 
-        let castling_rights: String = ['K', 'Q', 'k', 'q']
-            .into_iter()
-            .zip([CASTLE_W_SHORT, CASTLE_W_LONG, CASTLE_B_SHORT, CASTLE_B_LONG])
-            .filter(|(_, c)| self.can_castle & c != 0)
-            .map(|(x, _)| x)
-            .collect();
+        // X-FEN - the file of the rook if it is not the outermost one
+        let mut castling_rights = String::new();
+        for (i, c) in ['K', 'Q', 'k', 'q'].into_iter().enumerate() {
+            if self.can_castle & 1 << i == 0 {
+                continue;
+            }
+            let rsq = self.castle_rooks[i] as usize;
+            let rook = self[rsq];
+            let mut outside = if i % 2 == 0 {
+                (rsq % 8..rsq).step_by(8)
+            } else {
+                (rsq + 8..64).step_by(8)
+            };
+            castling_rights.push(if outside.any(|j| self[j] == rook) {
+                let file = (b'a' + 7 - rsq as u8 / 8) as char;
+                if i < 2 {
+                    file.to_ascii_uppercase()
+                } else {
+                    file
+                }
+            } else {
+                c
+            });
+        }
         s.push_str(if castling_rights.is_empty() {
             "-"
         } else {
@@ -393,7 +538,7 @@ impl Board {
         }
 
         // reversible moves,move nr
-        s.push_str(format!(" {} {}", self.half_moves() - 1, self.move_number()).as_str());
+        s.push_str(format!(" {} {}", self.half_moves(), self.move_number()).as_str());
         s
     }
 
@@ -433,8 +578,14 @@ impl Board {
         v
     }
 
+    /// Square behind a pawn that just advanced two squares - 0 if none
+    pub fn en_passant_sq(&self) -> u8 {
+        self.en_passant_sq
+    }
+
+    /// Half moves since the last capture or pawn move - for the fifty move rule
     pub fn half_moves(&self) -> usize {
-        self.half_move_clock + self.rep.iter().map(|(_, &v)| v).sum::<u8>() as usize
+        self.half_move_clock
     }
 
     pub fn update(&mut self, m: &Move) {
@@ -444,129 +595,144 @@ impl Board {
             self.hash,
             self.can_castle,
             self.en_passant_sq,
+            self.half_move_clock,
         ));
-        let hash;
+        self.history.push(self.hash);
+        self.half_move_clock = if m.en_passant()
+            || self[m.to() as usize] != EMPTY && !m.castle()
+            || self[m.frm() as usize].kind() == PAWN
+        {
+            0 // irreversible
+        } else {
+            self.half_move_clock + 1
+        };
+        self.update_bitmaps(m);
+        let mut hash = CASTLE_HASH[self.can_castle as usize] ^ en_passant_hash(self.en_passant_sq);
         self.en_passant_sq = 0;
-        self[m.to() as usize] = if m.castle() {
-            let (x, y) = if m.to() <= 15 {
-                (m.frm() - 24, m.frm() - 8) // short
+        // moving from or capturing on a king / rook home square loses castling rights
+        self.can_castle &= self.castle_masks[m.frm() as usize] & self.castle_masks[m.to() as usize];
+        if m.castle() {
+            let (kto, rto) = castle_squares(m.frm(), m.to());
+            let (king, rook) = (self[m.frm() as usize], self[m.to() as usize]);
+            hash ^= king.hashkey(m.frm())
+                ^ king.hashkey(kto)
+                ^ rook.hashkey(m.to())
+                ^ rook.hashkey(rto);
+            // cleared first - in Chess960 the king and rook can swap squares
+            self[m.frm() as usize] = EMPTY;
+            self[m.to() as usize] = EMPTY;
+            self[kto as usize] = king;
+            self[rto as usize] = rook;
+        } else {
+            self[m.to() as usize] = if m.transform() {
+                let p = Piece::new(m.promote_kind(), self.colour);
+                hash ^= p.hashkey(m.to())
+                    ^ self[m.frm() as usize].hashkey(m.frm())
+                    ^ self[m.to() as usize].hashkey(m.to());
+                p
+            } else if m.en_passant() {
+                // +9  +1 -7
+                // +8   0 -8
+                // +7  -1 -9
+                let x = match m.to() > m.frm() {
+                    true => m.frm() + 8,  // west
+                    false => m.frm() - 8, // east
+                };
+                hash ^= self[m.frm() as usize].hashkey(m.to())
+                    ^ self[m.frm() as usize].hashkey(m.frm())
+                    ^ self[x as usize].hashkey(x);
+                self[x as usize] = EMPTY;
+                self[m.frm() as usize]
             } else {
-                (m.frm() + 32, m.frm() + 8) // long
+                if self[m.frm() as usize].kind() == PAWN && m.frm().abs_diff(m.to()) == 2 {
+                    self.en_passant_sq = m.frm() + 2 * self.colour.as_u8() - 1;
+                }
+                hash ^= self[m.frm() as usize].hashkey(m.to())
+                    ^ self[m.frm() as usize].hashkey(m.frm())
+                    ^ self[m.to() as usize].hashkey(m.to());
+                self[m.frm() as usize]
             };
+            self[m.frm() as usize] = EMPTY;
+        }
+        self.material += m.val;
+        self.hash ^= hash
+            ^ WHITE_HASH
+            ^ CASTLE_HASH[self.can_castle as usize]
+            ^ en_passant_hash(self.en_passant_sq);
+        // self.bitmaps = self.board.to_bitmaps();
+        self.colour.flip();
+    }
 
-            self.bitmaps.kings |= 1 << m.to();
-            self.bitmaps.kings ^= 1 << m.frm();
-            self.bitmaps.pieces[self.colour.as_usize()] |= 1 << m.to();
-            self.bitmaps.pieces[self.colour.as_usize()] ^= 1 << m.frm();
-            self.bitmaps.pieces[self.colour.as_usize()] |= 1 << y;
-            self.bitmaps.pieces[self.colour.as_usize()] ^= 1 << x;
-
-            match self[m.frm() as usize] {
-                WKING => self.can_castle &= !CASTLE_W_SHORT & !CASTLE_W_LONG,
-                BKING => self.can_castle &= !CASTLE_B_SHORT & !CASTLE_B_LONG,
-                _ => panic!("not castle..."),
-            }
+    // move the pieces in the bitmaps - called before the squares are updated
+    fn update_bitmaps(&mut self, m: &Move) {
+        let (frm, to) = (m.frm() as usize, m.to() as usize);
+        let bm = &mut self.bitmaps;
+        if m.castle() {
+            let (kto, rto) = castle_squares(m.frm(), m.to());
+            let (king, rook) = (1 << frm ^ 1 << kto, 1 << to ^ 1 << rto);
+            bm.pieces[self.colour.as_usize()] ^= king ^ rook;
+            bm.kings ^= king;
+            bm.rooks ^= rook;
+            return;
+        }
+        let captured = if m.en_passant() {
+            if to > frm { frm + 8 } else { frm - 8 }
+        } else {
+            to
+        };
+        if self.squares[captured] != EMPTY {
+            bm.pieces[self.colour.opposite().as_usize()] ^= 1 << captured;
+            *bm.kind_mut(self.squares[captured].kind()) ^= 1 << captured;
+        }
+        bm.pieces[self.colour.as_usize()] ^= 1 << frm | 1 << to;
+        if m.transform() {
+            bm.pawns ^= 1 << frm;
+            *bm.kind_mut(m.promote_kind()) |= 1 << to;
+        } else {
+            *bm.kind_mut(self.squares[frm].kind()) ^= 1 << frm | 1 << to;
+        }
+    }
 
-            hash = self[m.frm() as usize].hashkey(m.to())
-                ^ self[m.frm() as usize].hashkey(m.frm())
-                ^ self[x as usize].hashkey(y)
-                ^ self[x as usize].hashkey(x);
-            self[y as usize] = self.squares[x as usize]; // move rook
-            self[x as usize] = EMPTY;
-            self[m.frm() as usize]
-        } else if m.transform() {
-            self.bitmaps.pieces[self.colour.as_usize()] |= 1 << m.to();
-            self.bitmaps.pieces[self.colour.as_usize()] ^= 1 << m.frm();
-            self.bitmaps.pawns ^= 1 << m.frm();
-            if self[m.to() as usize] != EMPTY {
-                // capture
-                let c = self[m.to() as usize].colour();
-                self.bitmaps.pieces[c.as_usize()] ^= 1 << m.to();
-            }
+    // pass the turn - the board is otherwise unchanged
+    pub fn null_update(&mut self) {
+        self.log_bms.push((
+            self.bitmaps,
+            EMPTY,
+            self.hash,
+            self.can_castle,
+            self.en_passant_sq,
+            self.half_move_clock,
+        ));
+        self.history.push(self.hash);
+        self.half_move_clock = 0; // no repetitions across a null move
+        self.hash ^= WHITE_HASH ^ en_passant_hash(self.en_passant_sq);
+        self.en_passant_sq = 0;
+        self.colour.flip();
+    }
 
-            let p = Piece::new(m.promote_kind(), self.colour);
-            hash = p.hashkey(m.to())
-                ^ self[m.frm() as usize].hashkey(m.frm())
-                ^ self[m.to() as usize].hashkey(m.to());
-            p
-        } else if m.en_passant() {
-            // +9  +1 -7
-            // +8   0 -8
-            // +7  -1 -9
-            let x = match m.to() > m.frm() {
-                true => m.frm() + 8,  // west
-                false => m.frm() - 8, // east
-            };
+    pub fn null_backdate(&mut self) {
+        let (_, _, hash, _, en_passant_sq, half_move_clock) = self.log_bms.pop().unwrap();
+        self.history.pop();
+        self.hash = hash;
+        self.half_move_clock = half_move_clock;
+        self.en_passant_sq = en_passant_sq;
+        self.colour.flip();
+    }
 
-            self.bitmaps.pieces[self.colour.as_usize()] |= 1 << m.to();
-            self.bitmaps.pieces[self.colour.as_usize()] ^= 1 << m.frm();
-            self.bitmaps.pieces[self.colour.opposite().as_usize()] ^= 1 << x;
-            self.bitmaps.pawns |= 1 << m.to();
-            self.bitmaps.pawns ^= 1 << m.frm();
-            self.bitmaps.pawns ^= 1 << x;
-
-            hash = self[m.frm() as usize].hashkey(m.to())
-                ^ self[m.frm() as usize].hashkey(m.frm())
-                ^ self[x as usize].hashkey(x);
-            self[x as usize] = EMPTY;
-            self[m.frm() as usize]
-        } else {
-            self.bitmaps.pieces[self.colour.as_usize()] |= 1 << m.to();
-            self.bitmaps.pieces[self.colour.as_usize()] ^= 1 << m.frm();
-            let c = self[m.to() as usize].colour();
-            match (self[m.frm() as usize].kind(), self[m.to() as usize].kind()) {
-                (PAWN, PAWN) => {
-                    self.bitmaps.pawns ^= 1 << m.frm();
-                    self.bitmaps.pieces[c.as_usize()] ^= 1 << m.to();
-                }
-                (PAWN, ROOK | BISHOP | QUEEN | KNIGHT) => {
-                    self.bitmaps.pawns |= 1 << m.to();
-                    self.bitmaps.pawns ^= 1 << m.frm();
-                    self.bitmaps.pieces[c.as_usize()] ^= 1 << m.to();
-                }
-                (PAWN, _) => {
-                    if m.frm().abs_diff(m.to()) == 2 {
-                        self.en_passant_sq = m.frm() + 2 * self.colour.as_u8() - 1;
-                    }
-                    self.bitmaps.pawns |= 1 << m.to();
-                    self.bitmaps.pawns ^= 1 << m.frm();
-                }
-                (KING, PAWN) => {
-                    self.bitmaps.pawns ^= 1 << m.to();
-                    self.bitmaps.kings |= 1 << m.to();
-                    self.bitmaps.kings ^= 1 << m.frm();
-                    self.bitmaps.pieces[c.as_usize()] ^= 1 << m.to();
-                }
-                (KING, ROOK | BISHOP | QUEEN | KNIGHT) => {
-                    self.bitmaps.kings |= 1 << m.to();
-                    self.bitmaps.kings ^= 1 << m.frm();
-                    self.bitmaps.pieces[c.as_usize()] ^= 1 << m.to();
-                }
-                (KING, _) => {
-                    self.bitmaps.kings |= 1 << m.to();
-                    self.bitmaps.kings ^= 1 << m.frm();
-                }
-                (_, PAWN) => {
-                    self.bitmaps.pawns ^= 1 << m.to();
-                    self.bitmaps.pieces[c.as_usize()] ^= 1 << m.to()
-                }
-                (_, ROOK | KNIGHT | QUEEN | BISHOP) => {
-                    self.bitmaps.pieces[c.as_usize()] ^= 1 << m.to()
-                }
-                _ => (),
-            }
+    // pieces other than pawns and king - without them zugzwang is likely
+    pub fn has_non_pawn_material(&self, colour: Colour) -> bool {
+        self.bitmaps.pieces[colour.as_usize()] & !(self.bitmaps.pawns | self.bitmaps.kings) != 0
+    }
 
-            hash = self[m.frm() as usize].hashkey(m.to())
-                ^ self[m.frm() as usize].hashkey(m.frm())
-                ^ self[m.to() as usize].hashkey(m.to());
-            self[m.frm() as usize]
-        };
-        self[m.frm() as usize] = EMPTY;
-        self.material += m.val;
-        self.rep_inc();
-        self.hash ^= hash ^ WHITE_HASH;
-        // self.bitmaps = self.board.to_bitmaps();
-        self.colour.flip();
+    /// Neither side can mate - a lone minor piece at most, or bishops all on one colour
+    pub fn insufficient_material(&self) -> bool {
+        let bms = &self.bitmaps;
+        if bms.pawns | bms.rooks | bms.queens != 0 {
+            return false;
+        }
+        (bms.knights | bms.bishops).count_ones() <= 1
+            || bms.knights == 0
+                && (bms.bishops & DARK_SQUARES == 0 || bms.bishops & !DARK_SQUARES == 0)
     }
 
     pub fn backdate(&mut self, m: &Move) {
@@ -578,25 +744,26 @@ impl Board {
             self.hash,
             self.can_castle,
             self.en_passant_sq,
+            self.half_move_clock,
         ) = bms;
+        self.history.pop();
         self.colour.flip();
         //self.hash ^= m.hash ^ WHITE_HASH;
-        self.rep_dec();
         if m.castle() {
-            let (frm, to) = if m.to() <= 15 {
-                (m.frm() - 24, m.frm() - 8) // short
+            let (kto, rto) = castle_squares(m.frm(), m.to());
+            let (king, rook) = (self[kto as usize], self[rto as usize]);
+            self[kto as usize] = EMPTY;
+            self[rto as usize] = EMPTY;
+            self[m.frm() as usize] = king;
+            self[m.to() as usize] = rook;
+        } else {
+            self[m.frm() as usize] = if m.transform() {
+                Piece::new(PAWN, self.colour)
             } else {
-                (m.frm() + 32, m.frm() + 8) // long
+                self[m.to() as usize]
             };
-            self[frm as usize] = self.squares[to as usize]; // move rook
-            self[to as usize] = EMPTY;
+            self[m.to() as usize] = capture;
         }
-        self[m.frm() as usize] = if m.transform() {
-            Piece::new(PAWN, self.colour)
-        } else {
-            self[m.to() as usize]
-        };
-        self[m.to() as usize] = capture;
 
         if m.en_passant() {
             let x = match m.to() > m.frm() {
@@ -682,44 +849,262 @@ impl Board {
     // true if !colour side can capture colour king
     pub fn in_check(&self, colour: Colour) -> bool {
         let bm_king = self.bitmaps.kings & self.bitmaps.pieces[colour.as_usize()];
-        let bm_board =
-            self.bitmaps.pieces[BLACK.as_usize()] | self.bitmaps.pieces[WHITE.as_usize()];
-        let opp = colour.opposite().as_usize();
-        self.squares
-            .iter()
-            .enumerate()
-            .filter(|(frm, _)| 1 << frm & self.bitmaps.pieces[opp] != 0)
-            .any(|(frm, &p)| match p.kind() {
-                KNIGHT => BM_KNIGHT_MOVES[frm] & bm_king != 0,
-                KING => BM_KING_MOVES[frm] & bm_king != 0,
-                PAWN => BM_PAWN_CAPTURES[opp][frm] & bm_king != 0,
-                ROOK => ray_check(frm, BM_ROOK_MOVES[frm], bm_board, bm_king),
-                BISHOP => ray_check(frm, BM_BISHOP_MOVES[frm], bm_board, bm_king),
-                QUEEN => ray_check(frm, BM_QUEEN_MOVES[frm], bm_board, bm_king),
-                _ => false,
-            })
+        self.attacked(bm_king, colour.opposite())
     }
 
-    pub fn moves(&self, in_check: bool, end_game: bool) -> Vec<Move> {
-        let mut v = Vec::with_capacity(50);
-        self.squares
-            .iter()
-            .enumerate()
-            .filter(|(frm, _)| 1 << frm & self.bitmaps.pieces[self.colour.as_usize()] != 0)
-            .for_each(|(frm, &p)| match p.kind() {
-                KNIGHT => self.knight_moves(&mut v, frm),
-                KING => self.king_moves(&mut v, frm, end_game, in_check),
-                PAWN => self.pawn_moves(&mut v, frm),
-                ROOK => self.ray_moves(&mut v, frm, BM_ROOK_MOVES[frm]),
-                BISHOP => self.ray_moves(&mut v, frm, BM_BISHOP_MOVES[frm]),
-                QUEEN => self.ray_moves(&mut v, frm, BM_QUEEN_MOVES[frm]),
-                _ => (),
-            });
+    // true if a piece of colour `by` attacks the square in bm (one square)
+    fn attacked(&self, bm: u64, by: Colour) -> bool {
+        bm != 0 && self.attacked_by(bm.trailing_zeros() as usize, self.bitmaps.board(), by)
+    }
+
+    // pieces of either colour attacking sq - given the occupied squares
+    fn attackers(&self, sq: usize, bm_board: u64) -> u64 {
+        let bms = &self.bitmaps;
+        let (white, black) = (bms.pieces[WHITE.as_usize()], bms.pieces[BLACK.as_usize()]);
+        // moves from sq reach exactly the pieces of the same kind that attack sq - pawns
+        // capture in one direction only, so look from sq as a pawn of the other colour
+        BM_KNIGHT_MOVES[sq] & bms.knights
+            | BM_KING_MOVES[sq] & bms.kings
+            | BM_PAWN_CAPTURES[WHITE.as_usize()][sq] & bms.pawns & black
+            | BM_PAWN_CAPTURES[BLACK.as_usize()][sq] & bms.pawns & white
+            | rook_attacks(sq, bm_board) & (bms.rooks | bms.queens)
+            | bishop_attacks(sq, bm_board) & (bms.bishops | bms.queens)
+    }
+
+    // true if a piece of colour `by` attacks sq - given the occupied squares.
+    // Cheaper than attackers, the sliders are only looked up if needed
+    #[inline]
+    fn attacked_by(&self, sq: usize, bm_board: u64, by: Colour) -> bool {
+        let bms = &self.bitmaps;
+        let bm_by = bms.pieces[by.as_usize()];
+        (BM_KNIGHT_MOVES[sq] & bms.knights
+            | BM_KING_MOVES[sq] & bms.kings
+            | BM_PAWN_CAPTURES[by.opposite().as_usize()][sq] & bms.pawns)
+            & bm_by
+            != 0
+            || rook_attacks(sq, bm_board) & (bms.rooks | bms.queens) & bm_by != 0
+            || bishop_attacks(sq, bm_board) & (bms.bishops | bms.queens) & bm_by != 0
+    }
+
+    // en passant takes two pawns off a rank - play it out on the bitmaps to see if the king is exposed
+    fn en_passant_legal(&self, frm: usize, to: usize, captured: usize) -> bool {
+        let bm_king = self.bitmaps.kings & self.bitmaps.pieces[self.colour.as_usize()];
+        if bm_king == 0 {
+            return true;
+        }
+        let bm_board = self.bitmaps.board() ^ (1 << frm | 1 << captured | 1 << to);
+        let bm_opp = self.bitmaps.pieces[self.colour.opposite().as_usize()] & !(1 << captured);
+        self.attackers(bm_king.trailing_zeros() as usize, bm_board) & bm_opp == 0
+    }
+
+    /// Number of leaf nodes of the legal move tree of the given depth
+    pub fn perft(&mut self, depth: u16) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        let mut moves = MoveList::new();
+        self.generate(&mut moves, false, GenType::All);
+        if depth == 1 {
+            return moves.len() as u64;
+        }
+        let mut n = 0;
+        for m in &moves {
+            self.update(m);
+            n += self.perft(depth - 1);
+            self.backdate(m);
+        }
+        n
+    }
+
+    /// Perft split by root move - for finding the move a generator bug hides under
+    pub fn divide(&mut self, depth: u16) -> Vec<(Move, u64)> {
+        let mut v = vec![];
+        for m in self.legal_moves(false) {
+            self.update(&m);
+            v.push((m, self.perft(depth.saturating_sub(1))));
+            self.backdate(&m);
+        }
+        v
+    }
+
+    /// Pseudo legal moves - may leave the king in check
+    pub fn moves(&self, in_check: bool, end_game: bool) -> MoveList {
+        let mut v = MoveList::new();
+        self.pseudo_moves(&mut v, in_check, end_game, GenType::All);
         v
     }
 
-    fn knight_moves(&self, v: &mut Vec<Move>, frm: usize) {
-        let mut b = BM_KNIGHT_MOVES[frm] & !self.bitmaps.pieces[self.colour.as_usize()];
+    fn pseudo_moves(&self, v: &mut MoveList, in_check: bool, end_game: bool, gen_type: GenType) {
+        let bm_own = self.bitmaps.pieces[self.colour.as_usize()];
+        let mut b = bm_own;
+        while b != 0 {
+            let frm = b.trailing_zeros() as usize;
+            b &= !(1 << frm);
+            self.piece_moves(v, frm, !bm_own, end_game, in_check, gen_type);
+        }
+    }
+
+    /// Legal moves - checkers and pinned pieces are worked out once up front,
+    /// so no move has to be tried on the board
+    pub fn legal_moves(&self, end_game: bool) -> MoveList {
+        let mut v = MoveList::new();
+        self.generate(&mut v, end_game, GenType::All);
+        v
+    }
+
+    /// Legal captures, en passant & promotions
+    pub fn captures(&self, end_game: bool) -> MoveList {
+        let mut v = MoveList::new();
+        self.generate(&mut v, end_game, GenType::Captures);
+        v
+    }
+
+    /// Legal moves that are not captures or promotions
+    pub fn quiets(&self, end_game: bool) -> MoveList {
+        let mut v = MoveList::new();
+        self.generate(&mut v, end_game, GenType::Quiets);
+        v
+    }
+
+    /// Legal replies to a check - king moves, captures of the checker and blocks
+    pub fn evasions(&self, end_game: bool) -> MoveList {
+        let mut v = MoveList::new();
+        self.generate_evasions(&mut v, end_game);
+        v
+    }
+
+    /// Append the legal moves of the given type to v
+    pub fn generate(&self, v: &mut MoveList, end_game: bool, gen_type: GenType) {
+        let bms = &self.bitmaps;
+        let bm_own = bms.pieces[self.colour.as_usize()];
+        let bm_opp = bms.pieces[self.colour.opposite().as_usize()];
+        let bm_king = bms.kings & bm_own;
+        if bm_king == 0 {
+            return self.pseudo_moves(v, false, end_game, gen_type);
+        }
+        let ksq = bm_king.trailing_zeros() as usize;
+        let bm_board = bms.board();
+        let checkers = self.attackers(ksq, bm_board) & bm_opp;
+        let pinned = self.pinned(ksq);
+
+        // a check is answered by capturing the checker or blocking - a double check by the king only
+        let targets = match checkers.count_ones() {
+            0 => !bm_own,
+            1 => BM_BETWEEN[ksq][checkers.trailing_zeros() as usize] | checkers,
+            _ => 0,
+        };
+        let king_targets = self.king_targets(ksq);
+
+        let mut b = bm_own;
+        while b != 0 {
+            let frm = b.trailing_zeros() as usize;
+            b &= !(1 << frm);
+            let targets = if frm == ksq {
+                king_targets
+            } else if pinned & 1 << frm != 0 {
+                targets & BM_LINE[ksq][frm]
+            } else {
+                targets
+            };
+            self.piece_moves(v, frm, targets, end_game, checkers != 0, gen_type);
+        }
+    }
+
+    /// Append the legal replies to a check to v - the king steps aside, or, in a single
+    /// check, a piece that is not pinned captures the checker or moves in between.
+    /// A pinned piece can never answer a check: it stays on the line through the king
+    pub fn generate_evasions(&self, v: &mut MoveList, end_game: bool) {
+        let bm_own = self.bitmaps.pieces[self.colour.as_usize()];
+        let bm_opp = self.bitmaps.pieces[self.colour.opposite().as_usize()];
+        let bm_king = self.bitmaps.kings & bm_own;
+        let ksq = bm_king.trailing_zeros() as usize;
+        let checkers = self.attackers(ksq, self.bitmaps.board()) & bm_opp;
+        debug_assert!(checkers != 0, "not in check: {}", self.to_fen());
+
+        self.king_moves(v, ksq, self.king_targets(ksq), end_game, false);
+        if checkers.count_ones() > 1 {
+            return;
+        }
+        let targets = BM_BETWEEN[ksq][checkers.trailing_zeros() as usize] | checkers;
+        let mut b = bm_own & !bm_king & !self.pinned(ksq);
+        while b != 0 {
+            let frm = b.trailing_zeros() as usize;
+            b &= !(1 << frm);
+            self.piece_moves(v, frm, targets, end_game, true, GenType::All);
+        }
+    }
+
+    // own pieces alone between the king and an enemy slider - they may only move along that line
+    fn pinned(&self, ksq: usize) -> u64 {
+        let bms = &self.bitmaps;
+        let bm_board = bms.board();
+        let snipers = bms.pieces[self.colour.opposite().as_usize()]
+            & (rook_attacks(ksq, 0) & (bms.rooks | bms.queens)
+                | bishop_attacks(ksq, 0) & (bms.bishops | bms.queens));
+        let mut pinned = 0;
+        let mut b = snipers;
+        while b != 0 {
+            let sq = b.trailing_zeros() as usize;
+            b &= !(1 << sq);
+            let between = BM_BETWEEN[ksq][sq] & bm_board;
+            if between.count_ones() == 1 {
+                pinned |= between & bms.pieces[self.colour.as_usize()];
+            }
+        }
+        pinned
+    }
+
+    // squares the king on ksq can step to - the king is taken off the board,
+    // it can not step back along a checking line
+    fn king_targets(&self, ksq: usize) -> u64 {
+        let bm_board = self.bitmaps.board();
+        let mut king_targets = 0;
+        let mut b = BM_KING_MOVES[ksq] & !self.bitmaps.pieces[self.colour.as_usize()];
+        while b != 0 {
+            let to = b.trailing_zeros() as usize;
+            b &= !(1 << to);
+            if !self.attacked_by(to, bm_board ^ 1 << ksq, self.colour.opposite()) {
+                king_targets |= 1 << to;
+            }
+        }
+        king_targets
+    }
+
+    // moves of the piece on frm to the target squares - castling & en passant are checked separately
+    fn piece_moves(
+        &self,
+        v: &mut MoveList,
+        frm: usize,
+        targets: u64,
+        end_game: bool,
+        in_check: bool,
+        gen_type: GenType,
+    ) {
+        let bm_board = self.bitmaps.board();
+        let bm_opp = self.bitmaps.pieces[self.colour.opposite().as_usize()];
+        let (pawn_targets, castle) = match gen_type {
+            GenType::All => (targets, !in_check),
+            GenType::Captures => (targets & (bm_opp | PROMOTION_SQUARES), false),
+            GenType::Quiets => (targets & !bm_opp & !PROMOTION_SQUARES, !in_check),
+        };
+        let targets = match gen_type {
+            GenType::All => targets,
+            GenType::Captures => targets & bm_opp,
+            GenType::Quiets => targets & !bm_opp,
+        };
+        match self.squares[frm].kind() {
+            KNIGHT => self.knight_moves(v, frm, targets),
+            KING => self.king_moves(v, frm, targets, end_game, castle),
+            PAWN => self.pawn_moves(v, frm, pawn_targets, gen_type != GenType::Quiets),
+            ROOK => self.ray_moves(v, frm, rook_attacks(frm, bm_board) & targets),
+            BISHOP => self.ray_moves(v, frm, bishop_attacks(frm, bm_board) & targets),
+            QUEEN => self.ray_moves(v, frm, queen_attacks(frm, bm_board) & targets),
+            _ => (),
+        }
+    }
+
+    fn knight_moves(&self, v: &mut MoveList, frm: usize, targets: u64) {
+        let mut b = BM_KNIGHT_MOVES[frm] & targets & !self.bitmaps.pieces[self.colour.as_usize()];
         while b != 0 {
             let to = b.trailing_zeros() as usize;
             b &= !(1 << to);
@@ -731,12 +1116,8 @@ impl Board {
         }
     }
 
-    fn ray_moves(&self, v: &mut Vec<Move>, frm: usize, moves: u64) {
-        let bm_board =
-            self.bitmaps.pieces[WHITE.as_usize()] | self.bitmaps.pieces[BLACK.as_usize()];
-        let bl = bm_blockers(frm, moves & bm_board);
-
-        let mut b = moves & !bl & !self.bitmaps.pieces[self.colour.as_usize()];
+    fn ray_moves(&self, v: &mut MoveList, frm: usize, moves: u64) {
+        let mut b = moves & !self.bitmaps.pieces[self.colour.as_usize()];
         while b != 0 {
             let to = b.trailing_zeros() as usize;
             b &= !(1 << to);
@@ -747,7 +1128,7 @@ impl Board {
         }
     }
 
-    fn pawn_moves(&self, v: &mut Vec<Move>, frm: usize) {
+    fn pawn_moves(&self, v: &mut MoveList, frm: usize, targets: u64, en_passant: bool) {
         let bm_board =
             self.bitmaps.pieces[WHITE.as_usize()] | self.bitmaps.pieces[BLACK.as_usize()];
         let cap = BM_PAWN_CAPTURES[self.colour.as_usize()][frm]
@@ -760,7 +1141,7 @@ impl Board {
         };
         let step2: u64 = step2 & BM_PAWN_STEP2[self.colour.as_usize()][frm] & !bm_board;
 
-        let mut b = cap | step1 | step2;
+        let mut b = (cap | step1 | step2) & targets;
         while b != 0 {
             let to = b.trailing_zeros() as usize;
             b &= !(1 << to);
@@ -793,12 +1174,15 @@ impl Board {
         }
 
         // en passant
-        if self.en_passant_sq > 0 {
+        if en_passant && self.en_passant_sq > 0 {
             let lto = self.en_passant_sq + 2 * self.colour.opposite().as_u8() - 1;
             let mut b = BM_PAWN_CAPTURES[self.colour.as_usize()][frm] & 1 << self.en_passant_sq;
             while b != 0 {
                 let to = b.trailing_zeros() as usize;
                 b &= !(1 << to);
+                if !self.en_passant_legal(frm, to, lto as usize) {
+                    continue;
+                }
 
                 v.push(Move {
                     data: pack_data(false, true, EMPTY, frm, to),
@@ -811,7 +1195,7 @@ impl Board {
         }
     }
 
-    fn king_moves(&self, v: &mut Vec<Move>, frm: usize, end_game: bool, in_check: bool) {
+    fn king_moves(&self, v: &mut MoveList, frm: usize, targets: u64, end_game: bool, castle: bool) {
         let bm_board =
             self.bitmaps.pieces[WHITE.as_usize()] | self.bitmaps.pieces[BLACK.as_usize()];
         // change king valuation in end_game
@@ -822,14 +1206,7 @@ impl Board {
             _ => panic!(),
         };
 
-        // castling
-        // check squares between K & R unoccupied
-        const WSHORT: u64 = 1 << 8 | 1 << 16;
-        const WLONG: u64 = 1 << 32 | 1 << 40 | 1 << 48;
-        const BSHORT: u64 = 1 << 15 | 1 << 23;
-        const BLONG: u64 = 1 << 55 | 1 << 47 | 1 << 39;
-
-        let mut b = BM_KING_MOVES[frm] & !self.bitmaps.pieces[self.colour.as_usize()];
+        let mut b = BM_KING_MOVES[frm] & targets & !self.bitmaps.pieces[self.colour.as_usize()];
         while b != 0 {
             let to = b.trailing_zeros() as usize;
             b &= !(1 << to);
@@ -840,83 +1217,63 @@ impl Board {
             })
         }
 
-        [
-            (
-                self.can_castle & CASTLE_W_SHORT != 0
-                    && frm == 24
-                    && !in_check
-                    && self[0] == WROOK
-                    && bm_board & WSHORT == 0,
-                WROOK,
-                8,
-                0,
-                16,
-            ),
-            (
-                self.can_castle & CASTLE_W_LONG != 0
-                    && frm == 24
-                    && !in_check
-                    && self[56] == WROOK
-                    && bm_board & WLONG == 0,
-                WROOK,
-                40,
-                56,
-                32,
-            ),
-            (
-                self.can_castle & CASTLE_B_SHORT != 0
-                    && frm == 31
-                    && !in_check
-                    && self[7] == BROOK
-                    && bm_board & BSHORT == 0,
-                BROOK,
-                15,
-                7,
-                23,
-            ),
-            (
-                self.can_castle & CASTLE_B_LONG != 0
-                    && frm == 31
-                    && !in_check
-                    && self[63] == BROOK
-                    && bm_board & BLONG == 0,
-                BROOK,
-                47,
-                63,
-                39,
-            ),
-        ]
-        .iter()
-        .filter(|(c, _, _, _, _)| *c)
-        .for_each(|(_, r, to, rfrm, rto)| {
-            v.push(Move {
-                data: pack_data(true, false, EMPTY, frm, *to),
-                val: p.val(*to as u8) - p.val(frm as u8) + r.val(*rto) - r.val(*rfrm),
-            })
-        })
+        // castling - stored as king takes rook, so the king and rook can start on any file (Chess960).
+        // The squares both pass must be empty, and those the king passes not attacked
+        if !castle {
+            return;
+        }
+        let first = if self.colour.is_white() { 0 } else { 2 };
+        for i in first..first + 2 {
+            let rfrm = self.castle_rooks[i] as usize;
+            if self.can_castle & 1 << i == 0 || self[rfrm].kind() != ROOK {
+                continue;
+            }
+            let (kto, rto) = castle_squares(frm as u8, rfrm as u8);
+            let (kto, rto) = (kto as usize, rto as usize);
+            let bm_others = bm_board & !(1 << frm | 1 << rfrm);
+            let king_path = BM_BETWEEN[frm][kto] | 1 << kto;
+            if (king_path | BM_BETWEEN[rfrm][rto] | 1 << rto) & bm_others != 0 {
+                continue;
+            }
+            let mut b = king_path;
+            let mut safe = true;
+            while b != 0 && safe {
+                let sq = b.trailing_zeros() as usize;
+                b &= !(1 << sq);
+                safe = !self.attacked_by(sq, bm_others, self.colour.opposite());
+            }
+            if safe {
+                let r = self[rfrm];
+                v.push(Move {
+                    data: pack_data(true, false, EMPTY, frm, rfrm),
+                    val: p.val(kto as u8) - p.val(frm as u8) + r.val(rto as u8) - r.val(rfrm as u8),
+                })
+            }
+        }
     }
 
     // count pseudo legal moves - ignoring en passant & castling
     fn count_moves(&self, colour: Colour) -> u32 {
-        let bm_board =
-            self.bitmaps.pieces[WHITE.as_usize()] | self.bitmaps.pieces[BLACK.as_usize()];
+        let bm_board = self.bitmaps.board();
         let bm_own = self.bitmaps.pieces[colour.as_usize()];
         let bm_opp = self.bitmaps.pieces[colour.opposite().as_usize()];
 
-        self.squares
-            .iter()
-            .enumerate()
-            .filter(|(frm, _)| 1 << frm & self.bitmaps.pieces[colour.as_usize()] != 0)
-            .map(|(frm, &p)| match p.kind() {
+        let mut n = 0;
+        let mut b = bm_own;
+        while b != 0 {
+            let frm = b.trailing_zeros() as usize;
+            b &= !(1 << frm);
+            n += match self.squares[frm].kind() {
                 KNIGHT => (BM_KNIGHT_MOVES[frm] & !bm_own).count_ones(),
                 KING => (BM_KING_MOVES[frm] & !bm_own).count_ones(),
                 PAWN => count_pawn_moves(frm, bm_opp, bm_board, colour),
-                ROOK => count_ray_moves(frm, BM_ROOK_MOVES[frm], bm_board, bm_own),
-                BISHOP => count_ray_moves(frm, BM_BISHOP_MOVES[frm], bm_board, bm_own),
-                QUEEN => count_ray_moves(frm, BM_QUEEN_MOVES[frm], bm_board, bm_own),
+                ROOK => (rook_attacks(frm, bm_board) & !bm_own).count_ones(),
+                BISHOP => (bishop_attacks(frm, bm_board) & !bm_own).count_ones(),
+                QUEEN => (queen_attacks(frm, bm_board) & !bm_own).count_ones(),
                 _ => 0,
-            })
-            .sum()
+            };
+        }
+        n
     }
 }
 
@@ -937,34 +1294,24 @@ fn count_pawn_moves(frm: usize, bm_opp: u64, bm_board: u64, colour: Colour) -> u
     (cap | step1 | step2).count_ones()
 }
 
-fn count_ray_moves(frm: usize, moves: u64, bm_board: u64, bm_own: u64) -> u32 {
-    (moves & !bm_own & !bm_blockers(frm, moves & bm_board)).count_ones()
-}
-
-fn ray_check(frm: usize, moves: u64, bm_board: u64, bm_king: u64) -> bool {
-    moves & bm_king & !bm_blockers(frm, moves & bm_board) != 0
-}
-
 const fn to_bitmaps(squares: &[Piece]) -> Bitmaps {
     let mut bm = Bitmaps {
         pieces: [0, 0],
         pawns: 0,
+        knights: 0,
+        bishops: 0,
+        rooks: 0,
+        queens: 0,
         kings: 0,
     };
     let mut i = 0;
     while i < squares.len() {
-        let c = squares[i].colour();
-        match squares[i].kind() {
-            ROOK | KNIGHT | BISHOP | QUEEN => bm.pieces[c.as_usize()] |= 1 << i,
-            PAWN => {
-                bm.pieces[c.as_usize()] |= 1 << i;
-                bm.pawns |= 1 << i
-            }
-            KING => {
-                bm.pieces[c.as_usize()] |= 1 << i;
-                bm.kings |= 1 << i
+        match squares[i] {
+            EMPTY => (),
+            p => {
+                bm.pieces[p.colour().as_usize()] |= 1 << i;
+                *bm.kind_mut(p.kind()) |= 1 << i;
             }
-            _ => (),
         }
         i += 1;
     }
@@ -991,11 +1338,37 @@ pub const fn abs_material(squares: &[Piece]) -> i16 {
     val
 }
 
-pub const fn calc_hash(squares: &[Piece], colour: Colour) -> u64 {
+// king and rook destinations of a castling move, which is stored as king takes rook -
+// the king ends on the g or c file and the rook next to it, from any files (Chess960)
+const fn castle_squares(frm: u8, to: u8) -> (u8, u8) {
+    let rank = frm % 8;
+    if to < frm {
+        (8 + rank, 16 + rank) // short
+    } else {
+        (40 + rank, 32 + rank) // long
+    }
+}
+
+// keyed by file - 0 (h1) is never an en passant square and means none
+const fn en_passant_hash(en_passant_sq: u8) -> u64 {
+    if en_passant_sq == 0 {
+        0
+    } else {
+        EN_PASSANT_HASH[en_passant_sq as usize / 8]
+    }
+}
+
+pub const fn calc_hash(
+    squares: &[Piece],
+    colour: Colour,
+    can_castle: u8,
+    en_passant_sq: u8,
+) -> u64 {
     let mut key = match colour {
         WHITE => WHITE_HASH,
         _ => 0,
     };
+    key ^= CASTLE_HASH[can_castle as usize] ^ en_passant_hash(en_passant_sq);
 
     let mut i = 0;
     while i < squares.len() {
@@ -1031,11 +1404,13 @@ fn from_fen(s: &str) -> [Piece; 64] {
 #[cfg(test)]
 mod tests {
     use crate::benchmark::*;
+    use crate::movepick::is_quiet;
     use crate::*;
+    use std::collections::HashSet;
 
     #[test]
     fn test_en_passant() {
-        let board = Board::from_fen(GUNDERSEN_FAUL[1].0);
+        let board = Board::from_fen(GUNDERSEN_FAUL[1].0).unwrap();
         let mut game = Game::new(board);
         let moves = game.legal_moves();
         let (frm, to) = misc::str2move("g7g5").unwrap();
@@ -1054,4 +1429,356 @@ mod tests {
         let moves = game.legal_moves();
         assert_eq!(moves.len(), 0);
     }
+
+    #[test]
+    fn test_null_move() {
+        let mut board =
+            Board::from_fen("rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 2")
+                .unwrap();
+        let (fen, hash) = (board.to_fen(), board.hash);
+        board.null_update();
+        assert_ne!(board.hash, hash);
+        assert!(!board.to_fen().contains("d6"));
+        assert_eq!(
+            board.colour,
+            Board::from_fen(&fen).unwrap().colour.opposite()
+        );
+        board.null_backdate();
+        assert_eq!((board.to_fen(), board.hash), (fen, hash));
+
+        let board = Board::from_fen("4k3/pppp4/8/8/8/8/PPPP4/4K1N1 w - - 0 1").unwrap();
+        assert!(board.has_non_pawn_material(WHITE));
+        assert!(!board.has_non_pawn_material(BLACK));
+    }
+
+    #[test]
+    fn test_repetition() {
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/4P3/4K1N1 w - - 7 20").unwrap();
+        let mut moves = vec![];
+        let mut play = |board: &mut Board, s: &str| {
+            let frmto = misc::str2move(s).unwrap();
+            let m = board
+                .legal_moves(false)
+                .into_iter()
+                .find(|m| (m.frm(), m.to()) == frmto);
+            board.update(&m.unwrap());
+            moves.push(m.unwrap());
+        };
+
+        for s in ["g1f3", "e8d8", "f3g1", "d8e8"] {
+            play(&mut board, s);
+        }
+        assert_eq!((board.rep_count(), board.half_moves()), (2, 11));
+        assert!(board.is_repetition());
+        assert!(board.to_fen().ends_with(" - 11 10"));
+        for s in ["g1f3", "e8d8", "f3g1", "d8e8"] {
+            play(&mut board, s);
+        }
+        assert_eq!(board.rep_count(), 3);
+
+        // a pawn move - the earlier positions can not come back
+        play(&mut board, "e2e3");
+        assert_eq!((board.rep_count(), board.half_moves()), (1, 0));
+        board.null_update();
+        board.null_update();
+        assert!(!board.is_repetition());
+        board.null_backdate();
+        board.null_backdate();
+
+        for m in moves.iter().rev() {
+            board.backdate(m);
+        }
+        assert_eq!((board.rep_count(), board.half_moves()), (1, 7));
+    }
+
+    #[test]
+    fn test_incremental_hash() {
+        use rand::prelude::IndexedRandom;
+        let check = |board: &Board| {
+            let hash = calc_hash(
+                &board.squares,
+                board.colour,
+                board.can_castle,
+                board.en_passant_sq,
+            );
+            assert_eq!(board.hash, hash, "{}", board.to_fen());
+            assert_eq!(board.hash, Board::from_fen(&board.to_fen()).unwrap().hash);
+            assert_eq!(board.bitmaps, board.to_bitmaps());
+        };
+
+        let mut rng = rand::rng();
+        for _ in 0..100 {
+            let mut board = Board::default();
+            let mut played = vec![];
+            for _ in 0..80 {
+                let colour = board.colour;
+                let moves: Vec<Move> = board
+                    .moves(board.in_check(colour), false)
+                    .into_iter()
+                    .filter(|m| {
+                        board.update(m);
+                        let legal = !board.in_check(colour);
+                        board.backdate(m);
+                        legal
+                    })
+                    .collect();
+                let Some(m) = moves.choose(&mut rng) else {
+                    break;
+                };
+                board.update(m);
+                check(&board);
+                played.push(*m);
+            }
+            for m in played.iter().rev() {
+                board.backdate(m);
+                check(&board);
+            }
+        }
+
+        // same pieces, different castling rights & en passant square
+        let fens = [
+            "r3k2r/8/8/8/4pP2/8/8/R3K2R b KQkq f3 0 1",
+            "r3k2r/8/8/8/4pP2/8/8/R3K2R b KQkq - 0 1",
+            "r3k2r/8/8/8/4pP2/8/8/R3K2R b Kkq f3 0 1",
+            "r3k2r/8/8/8/4pP2/8/8/R3K2R b - f3 0 1",
+        ];
+        let hashes: HashSet<u64> = fens
+            .iter()
+            .map(|fen| Board::from_fen(fen).unwrap().hash)
+            .collect();
+        assert_eq!(hashes.len(), fens.len());
+    }
+
+    // https://www.chessprogramming.org/Perft_Results
+    #[test]
+    fn test_perft() {
+        let positions = [
+            (ROOT_FEN, [20, 400, 8902, 197281]),
+            (
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                [48, 2039, 97862, 0],
+            ),
+            (
+                "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+                [14, 191, 2812, 43238],
+            ),
+            (
+                "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+                [6, 264, 9467, 0],
+            ),
+            (
+                "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+                [44, 1486, 62379, 0],
+            ),
+            (
+                "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+                [46, 2079, 89890, 0],
+            ),
+            // Chess960
+            (
+                "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+                [21, 528, 12189, 326672],
+            ),
+            (
+                "b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9",
+                [20, 479, 10471, 0],
+            ),
+            (
+                "qbbnnrkr/2pp2pp/p7/1p2pp2/8/P3PP2/1PPP1KPP/QBBNNR1R w hf - 0 9",
+                [22, 593, 13440, 0],
+            ),
+        ];
+        for (fen, counts) in positions {
+            let mut board = Board::from_fen(fen).unwrap();
+            let before = (board.hash, board.to_fen());
+            for (depth, &n) in counts.iter().enumerate().filter(|(_, n)| **n > 0) {
+                assert_eq!(
+                    board.perft(depth as u16 + 1),
+                    n,
+                    "{fen} depth {}",
+                    depth + 1
+                );
+            }
+            assert_eq!((board.hash, board.to_fen()), before);
+
+            let divide = board.divide(2);
+            assert_eq!(divide.len() as u64, counts[0]);
+            assert_eq!(divide.iter().map(|(_, n)| n).sum::<u64>(), counts[1]);
+        }
+    }
+
+    #[test]
+    fn test_chess960() {
+        // Shredder-FEN in, X-FEN out - a file letter only if the rook is not the outermost one
+        for (fen, xfen) in [
+            (
+                "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+                "KQkq",
+            ),
+            ("4k3/8/8/8/8/8/8/RR2K3 w B - 0 1", "B"),
+            ("1r2k2r/8/8/8/8/8/8/4K3 b kq - 0 1", "kq"),
+            ("1r2k2r/8/8/8/8/8/8/4K3 b a - 0 1", "-"),
+        ] {
+            let board = Board::from_fen(fen).unwrap();
+            assert_eq!(board.to_fen().split(' ').nth(2), Some(xfen), "{fen}");
+            assert_eq!(Board::from_fen(&board.to_fen()).unwrap().hash, board.hash);
+        }
+
+        // king and rook swap squares - and the king that castles without moving
+        for (fen, after) in [
+            (
+                "4k3/8/8/8/8/8/8/5KR1 w K - 0 1",
+                "4k3/8/8/8/8/8/8/5RK1 b - - 1 1",
+            ),
+            (
+                "4k3/8/8/8/8/8/8/6KR w K - 0 1",
+                "4k3/8/8/8/8/8/8/5RK1 b - - 1 1",
+            ),
+            (
+                "4k3/8/8/8/8/8/8/RK6 w Q - 0 1",
+                "4k3/8/8/8/8/8/8/2KR4 b - - 1 1",
+            ),
+        ] {
+            let mut board = Board::from_fen(fen).unwrap();
+            let m = board
+                .legal_moves(false)
+                .into_iter()
+                .find(|m| m.castle())
+                .unwrap();
+            assert_eq!(board[m.to() as usize].kind(), ROOK);
+            assert!(!m.is_null());
+            let before = (board.hash, board.to_fen());
+            board.update(&m);
+            assert_eq!(board.to_fen(), after);
+            assert_eq!(board.hash, Board::from_fen(after).unwrap().hash);
+            assert_eq!(board.bitmaps, board.to_bitmaps());
+            board.backdate(&m);
+            assert_eq!((board.hash, board.to_fen()), before);
+        }
+
+        // the king stays on c1 - which the rook on a1 sees once the castling rook has left b1
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/rRK5 w Q - 0 1").unwrap();
+        assert!(!board.legal_moves(false).iter().any(|m| m.castle()));
+    }
+
+    #[test]
+    fn test_legal_moves() {
+        use rand::prelude::IndexedRandom;
+        // the legal generator must agree with trying every pseudo legal move on the board
+        let check = |board: &mut Board| {
+            let colour = board.colour;
+            let mut pseudo: Vec<String> = board
+                .moves(board.in_check(colour), false)
+                .into_iter()
+                .filter(|m| {
+                    board.update(m);
+                    let legal = !board.in_check(colour);
+                    board.backdate(m);
+                    legal
+                })
+                .map(|m| m.to_string())
+                .collect();
+            let legal = board.legal_moves(false);
+            let mut l: Vec<String> = legal.iter().map(|m| m.to_string()).collect();
+            pseudo.sort();
+            l.sort();
+            assert_eq!(l, pseudo, "{}", board.to_fen());
+            if board.in_check(colour) {
+                let mut e: Vec<String> = board
+                    .evasions(false)
+                    .iter()
+                    .map(|m| m.to_string())
+                    .collect();
+                e.sort();
+                assert_eq!(e, l, "{}", board.to_fen());
+            }
+
+            // the stages split the legal moves
+            let captures = board.captures(false);
+            assert!(captures.iter().all(|m| !is_quiet(board, m)));
+            let mut staged: Vec<String> = captures
+                .iter()
+                .chain(board.quiets(false).iter())
+                .map(|m| m.to_string())
+                .collect();
+            staged.sort();
+            assert_eq!(staged, l, "{}", board.to_fen());
+            legal
+        };
+
+        // en passant exposing the king along the rank or taking the checker, pinned pieces,
+        // double check
+        for fen in [
+            "8/8/8/8/k2Pp2Q/8/8/3K4 b - d3 0 1",
+            "8/8/8/2k5/3Pp3/8/8/4K3 b - d3 0 1",
+            "4k3/8/8/2KPp2r/8/8/8/8 w - e6 0 1",
+            "4k3/4r3/8/8/8/8/4B3/4K3 w - - 0 1",
+            "4k3/8/8/8/1b6/8/3N4/4K3 w - - 0 1",
+            "4k3/8/8/8/8/5n2/8/r3K2R w K - 0 1",
+            "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1",
+        ] {
+            check(&mut Board::from_fen(fen).unwrap());
+        }
+
+        let mut rng = rand::rng();
+        for _ in 0..50 {
+            let mut board = Board::default();
+            for _ in 0..100 {
+                let moves = check(&mut board);
+                let Some(m) = moves.choose(&mut rng) else {
+                    break;
+                };
+                board.update(m);
+            }
+        }
+    }
+
+    #[test]
+    fn test_uci_moves() {
+        let uci = |fen: &str, s: &str| {
+            let board = Board::from_fen(fen).unwrap();
+            Move::from_uci(&board, s)
+        };
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert_eq!(uci(start, "e2e4").unwrap().to_uci(false), "e2e4");
+        assert_eq!(
+            uci(start, "e2e5"),
+            Err(UciMoveError::Illegal("e2e5".to_string()))
+        );
+        for s in ["e2e", "e2e4qq", "e2e4x", "i2e4"] {
+            assert_eq!(uci(start, s), Err(UciMoveError::Syntax(s.to_string())));
+        }
+
+        // promotions - the piece is required
+        let fen = "3r3k/4P3/8/8/8/8/8/K7 w - - 0 1";
+        let m = uci(fen, "e7d8n").unwrap();
+        assert!(m.transform() && m.promote_kind() == KNIGHT);
+        assert!(uci(fen, "e7e8").is_err());
+
+        // castling - the king's move or king takes rook
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        let (short, long) = (uci(fen, "e1g1").unwrap(), uci(fen, "e1c1").unwrap());
+        assert!(short.castle() && long.castle());
+        assert_eq!(uci(fen, "e1h1"), Ok(short));
+        assert_eq!(
+            (short.to_uci(false), short.to_uci(true)),
+            ("e1g1".into(), "e1h1".into())
+        );
+
+        // Chess960 - b1c1 is a king move, castling has to be written b1a1
+        let fen = "4k3/8/8/8/8/8/8/RK6 w Q - 0 1";
+        assert!(uci(fen, "b1a1").unwrap().castle());
+        assert!(!uci(fen, "b1c1").unwrap().castle());
+
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+        ] {
+            let board = Board::from_fen(fen).unwrap();
+            for m in board.legal_moves(board.is_end_game()) {
+                assert_eq!(Move::from_uci(&board, &m.to_uci(true)), Ok(m), "{fen}");
+            }
+        }
+    }
 }
@@ -0,0 +1,163 @@
+// Fixed capacity list of moves - lives on the stack, so generating moves does not allocate.
+// No legal chess position has more than 218 moves.
+
+use crate::mgen::Move;
+use std::fmt;
+use std::mem::MaybeUninit;
+use std::ops::{Deref, DerefMut};
+
+pub const MAX_MOVES: usize = 256;
+
+// left uninitialised - filling 256 entries costs more than generating the moves
+#[derive(Clone)]
+pub struct MoveList {
+    moves: [MaybeUninit<Move>; MAX_MOVES],
+    len: usize,
+}
+
+impl MoveList {
+    pub const fn new() -> Self {
+        MoveList {
+            moves: [MaybeUninit::uninit(); MAX_MOVES],
+            len: 0,
+        }
+    }
+
+    #[inline(always)]
+    pub fn push(&mut self, m: Move) {
+        self.moves[self.len].write(m);
+        self.len += 1;
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Keep the moves for which f is true - in their original order
+    pub fn retain(&mut self, mut f: impl FnMut(&Move) -> bool) {
+        let mut n = 0;
+        for i in 0..self.len {
+            let m = self[i];
+            if f(&m) {
+                self.moves[n].write(m);
+                n += 1;
+            }
+        }
+        self.len = n;
+    }
+}
+
+impl Default for MoveList {
+    fn default() -> Self {
+        MoveList::new()
+    }
+}
+
+// slice methods - len, iter, sort, indexing..
+impl Deref for MoveList {
+    type Target = [Move];
+    #[inline(always)]
+    fn deref(&self) -> &[Move] {
+        // SAFETY: the first len entries have been written
+        unsafe { self.moves[..self.len].assume_init_ref() }
+    }
+}
+
+impl DerefMut for MoveList {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut [Move] {
+        // SAFETY: the first len entries have been written
+        unsafe { self.moves[..self.len].assume_init_mut() }
+    }
+}
+
+impl fmt::Debug for MoveList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl Extend<Move> for MoveList {
+    fn extend<T: IntoIterator<Item = Move>>(&mut self, iter: T) {
+        for m in iter {
+            self.push(m);
+        }
+    }
+}
+
+impl FromIterator<Move> for MoveList {
+    fn from_iter<T: IntoIterator<Item = Move>>(iter: T) -> Self {
+        let mut l = MoveList::new();
+        l.extend(iter);
+        l
+    }
+}
+
+pub struct IntoIter {
+    list: MoveList,
+    next: usize,
+}
+
+impl Iterator for IntoIter {
+    type Item = Move;
+
+    #[inline]
+    fn next(&mut self) -> Option<Move> {
+        if self.next < self.list.len {
+            self.next += 1;
+            Some(self.list[self.next - 1])
+        } else {
+            None
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = self.list.len - self.next;
+        (n, Some(n))
+    }
+}
+
+impl IntoIterator for MoveList {
+    type Item = Move;
+    type IntoIter = IntoIter;
+    fn into_iter(self) -> IntoIter {
+        IntoIter {
+            list: self,
+            next: 0,
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a MoveList {
+    type Item = &'a Move;
+    type IntoIter = std::slice::Iter<'a, Move>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mgen::Board;
+
+    #[test]
+    fn test_move_list() {
+        let board = Board::default();
+        let mut l = board.legal_moves(false);
+        assert_eq!(l.len(), 20);
+        let v: Vec<Move> = l.clone().into_iter().collect();
+        assert_eq!(&v[..], &l[..]);
+
+        l.sort_unstable_by_key(|m| m.to_string());
+        assert_eq!(l[0].to_string(), "a2a3");
+        l.retain(|m| m.to_string().starts_with('g'));
+        let s: Vec<String> = l.iter().map(|m| m.to_string()).collect();
+        assert_eq!(s, ["g1f3", "g1h3", "g2g3", "g2g4"]);
+
+        let mut l: MoveList = v.iter().copied().filter(|m| m.frm() == 8).collect();
+        assert_eq!(l.len(), 2);
+        l.clear();
+        assert!(l.is_empty());
+    }
+}
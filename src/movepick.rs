@@ -0,0 +1,320 @@
+// Move ordering for the search - the earlier a good move is tried, the more alpha-beta prunes.
+// Order: transposition table move, captures & promotions (MVV-LVA), killer moves,
+// counter move, and finally the quiet moves by history score.
+// Generation is staged - the quiet moves are only generated once the captures are used up,
+// so a node that is cut off by a capture never pays for them.
+
+use crate::mgen::{Board, GenType, Move};
+use crate::movelist::{MAX_MOVES, MoveList};
+use crate::val::*;
+use core::cmp::Reverse;
+
+const TT_KEY: i32 = 1 << 30;
+const CAPTURE_KEY: i32 = 1 << 28;
+const KILLER_KEY: i32 = 1 << 27;
+const COUNTER_KEY: i32 = 1 << 26;
+const HISTORY_MAX: i32 = 1 << 20; // history scores stay below the counter move
+
+// piece values for MVV-LVA
+const fn kind_value(kind: u8) -> i32 {
+    match kind {
+        PAWN => 1,
+        KNIGHT | BISHOP => 3,
+        ROOK => 5,
+        QUEEN => 9,
+        KING => 10,
+        _ => 0,
+    }
+}
+
+#[inline]
+pub fn is_quiet(board: &Board, m: &Move) -> bool {
+    (board[m.to() as usize] == EMPTY || m.castle()) && !m.en_passant() && !m.transform()
+}
+
+/// Best material gain for the side to move first - Move::val is from white's point of view
+pub fn sort_by_val(moves: &mut [Move], colour: Colour) {
+    if colour.is_white() {
+        moves.sort_unstable_by_key(|m| Reverse(m.val)); // decreasing
+    } else {
+        moves.sort_unstable_by_key(|m| m.val); // increasing
+    }
+}
+
+/// Killer, history & counter move tables - learned from the beta cutoffs of quiet moves.
+pub struct Heuristics {
+    killers: Vec<[Option<Move>; 2]>,   // per ply
+    history: Vec<[[i32; 64]; 64]>,     // per colour - [frm][to]
+    counters: Vec<[Option<Move>; 64]>, // [last.frm][last.to] - reply to the previous move
+}
+
+impl Heuristics {
+    pub fn new(max_ply: usize) -> Self {
+        Heuristics {
+            killers: vec![[None; 2]; max_ply + 1],
+            history: vec![[[0; 64]; 64]; 2],
+            counters: vec![[None; 64]; 64],
+        }
+    }
+
+    /// Forget the killers and fade the history - called before each new search.
+    pub fn age(&mut self) {
+        self.killers.iter_mut().for_each(|k| *k = [None; 2]);
+        self.history
+            .iter_mut()
+            .flatten()
+            .flatten()
+            .for_each(|h| *h /= 8);
+    }
+
+    /// A quiet move caused a beta cutoff
+    pub fn cutoff(&mut self, colour: Colour, ply: usize, depth: u16, last: &Move, m: &Move) {
+        let k = &mut self.killers[ply];
+        if k[0] != Some(*m) {
+            k[1] = k[0];
+            k[0] = Some(*m);
+        }
+
+        let h = &mut self.history[colour.as_usize()][m.frm() as usize][m.to() as usize];
+        *h += depth as i32 * depth as i32;
+        if *h >= HISTORY_MAX {
+            self.history
+                .iter_mut()
+                .flatten()
+                .flatten()
+                .for_each(|h| *h /= 2);
+        }
+
+        if !last.is_null() {
+            self.counters[last.frm() as usize][last.to() as usize] = Some(*m);
+        }
+    }
+
+    fn killer(&self, ply: usize, m: &Move) -> Option<usize> {
+        self.killers[ply].iter().position(|k| *k == Some(*m))
+    }
+}
+
+/// Hands out moves best first. Moves are ranked up front and picked by
+/// selection, so the ordering work is only done for moves that get searched.
+pub struct MovePicker {
+    moves: MoveList,
+    keys: [i32; MAX_MOVES],
+    next: usize,
+    tt_move: Option<Move>,
+    ply: usize,
+    last: Move,
+    quiets: Option<bool>, // end_game flag while the quiet moves are still to be generated
+}
+
+impl MovePicker {
+    /// `moves` should be pre-sorted by Move::val - the tie break for quiet moves
+    pub fn new(
+        board: &Board,
+        moves: MoveList,
+        tt_move: Option<Move>,
+        heuristics: &Heuristics,
+        ply: usize,
+        last: &Move,
+    ) -> Self {
+        let mut picker = MovePicker {
+            moves,
+            keys: [0; MAX_MOVES],
+            next: 0,
+            tt_move,
+            ply,
+            last: *last,
+            quiets: None,
+        };
+        picker.rank(board, heuristics, 0);
+        picker
+    }
+
+    /// Legal moves generated in stages - captures first, quiet moves when the captures
+    /// are used up. In check, or if the transposition table move is quiet, all at once.
+    /// Moves are taken with next_move
+    pub fn staged(
+        board: &Board,
+        end_game: bool,
+        tt_move: Option<Move>,
+        heuristics: &Heuristics,
+        ply: usize,
+        last: &Move,
+    ) -> Self {
+        // generated straight into the picker - no copying of the move list
+        let mut picker = MovePicker::new(board, MoveList::new(), tt_move, heuristics, ply, last);
+        let moves = &mut picker.moves;
+        if board.in_check(board.colour) {
+            board.generate_evasions(moves, end_game);
+        } else {
+            board.generate(moves, end_game, GenType::Captures);
+            match tt_move {
+                Some(tt) if !moves.iter().any(|m| m.data == tt.data) => {
+                    moves.clear();
+                    board.generate(moves, end_game, GenType::All);
+                }
+                _ => picker.quiets = Some(end_game),
+            }
+        }
+        sort_by_val(&mut picker.moves, board.colour);
+        picker.rank(board, heuristics, 0);
+        picker
+    }
+
+    // rank the moves from i on
+    fn rank(&mut self, board: &Board, heuristics: &Heuristics, i: usize) {
+        let history = &heuristics.history[board.colour.as_usize()];
+        let counter = if self.last.is_null() {
+            None
+        } else {
+            heuristics.counters[self.last.frm() as usize][self.last.to() as usize]
+        };
+        for (j, m) in self.moves.iter().enumerate().skip(i) {
+            self.keys[j] = if self.tt_move.is_some_and(|tt| tt.data == m.data) {
+                TT_KEY
+            } else if !is_quiet(board, m) {
+                let victim = if m.en_passant() {
+                    PAWN
+                } else {
+                    board[m.to() as usize].kind()
+                };
+                let promotion = if m.transform() {
+                    kind_value(m.promote_kind())
+                } else {
+                    0
+                };
+                CAPTURE_KEY + 16 * (kind_value(victim) + promotion)
+                    - kind_value(board[m.frm() as usize].kind())
+            } else if let Some(i) = heuristics.killer(self.ply, m) {
+                KILLER_KEY - i as i32
+            } else if counter == Some(*m) {
+                COUNTER_KEY
+            } else {
+                history[m.frm() as usize][m.to() as usize]
+            };
+        }
+    }
+
+    /// Next best move - generating the quiet moves of a staged picker when needed.
+    /// board must be in the position the picker was made for
+    pub fn next_move(&mut self, board: &Board, heuristics: &Heuristics) -> Option<Move> {
+        if self.next >= self.moves.len()
+            && let Some(end_game) = self.quiets.take()
+        {
+            let n = self.moves.len();
+            board.generate(&mut self.moves, end_game, GenType::Quiets);
+            sort_by_val(&mut self.moves[n..], board.colour);
+            self.rank(board, heuristics, n);
+        }
+        self.next()
+    }
+
+    /// Number of moves generated so far
+    pub fn n_generated(&self) -> usize {
+        self.moves.len()
+    }
+
+    /// Number of moves handed out so far
+    pub fn n_picked(&self) -> usize {
+        self.next
+    }
+
+    /// True if the last move handed out was ranked as a quiet, non-killer move
+    pub fn last_was_late(&self) -> bool {
+        self.next > 0 && self.keys[self.next - 1] < COUNTER_KEY
+    }
+}
+
+impl Iterator for MovePicker {
+    type Item = Move;
+
+    fn next(&mut self) -> Option<Move> {
+        let i = self.next;
+        if i >= self.moves.len() {
+            return None;
+        }
+        let mut best = i;
+        for j in i + 1..self.moves.len() {
+            if self.keys[j] > self.keys[best] {
+                best = j;
+            }
+        }
+        // rotate rather than swap - keeps equally ranked moves in their original order
+        self.moves[i..=best].rotate_right(1);
+        self.keys[i..=best].rotate_right(1);
+        self.next += 1;
+        Some(self.moves[i])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mgen::NULL_MOVE;
+    use crate::misc::str2move;
+
+    fn find(moves: &[Move], s: &str) -> Move {
+        let (frm, to) = str2move(s).unwrap();
+        *moves
+            .iter()
+            .find(|m| (m.frm(), m.to()) == (frm, to))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_ordering() {
+        // white can take the queen with the pawn or the knight
+        let board = Board::from_fen("4k3/8/8/3q4/4P3/2N2p2/8/4K3 w - - 0 1").unwrap();
+        let moves = board.moves(false, false);
+        let tt_move = find(&moves, "e1d2");
+        let killer = find(&moves, "c3b5");
+        let mut heuristics = Heuristics::new(4);
+        heuristics.cutoff(WHITE, 2, 3, &NULL_MOVE, &killer);
+
+        let picker = MovePicker::new(
+            &board,
+            moves.clone(),
+            Some(tt_move),
+            &heuristics,
+            2,
+            &NULL_MOVE,
+        );
+        let order: Vec<Move> = picker.collect();
+        assert_eq!(order.len(), moves.len());
+        assert_eq!(order[0], tt_move);
+        assert_eq!(order[1], find(&moves, "e4d5")); // pawn takes queen
+        assert_eq!(order[2], find(&moves, "c3d5")); // knight takes queen
+        assert_eq!(order[3], killer);
+    }
+
+    #[test]
+    fn test_staged() {
+        let board = Board::from_fen("4k3/8/8/3q4/4P3/2N2p2/8/4K3 w - - 0 1").unwrap();
+        let heuristics = Heuristics::new(4);
+        let mut legal: Vec<String> = board
+            .legal_moves(false)
+            .iter()
+            .map(|m| m.to_string())
+            .collect();
+        legal.sort();
+
+        // captures first, the quiet moves generated when they run out
+        let mut picker = MovePicker::staged(&board, false, None, &heuristics, 0, &NULL_MOVE);
+        assert_eq!(picker.n_generated(), 2); // e4d5 & c3d5
+        let mut order = vec![];
+        while let Some(m) = picker.next_move(&board, &heuristics) {
+            order.push(m);
+        }
+        assert!(order[..2].iter().all(|m| !is_quiet(&board, m)));
+        let mut picked: Vec<String> = order.iter().map(|m| m.to_string()).collect();
+        picked.sort();
+        assert_eq!(picked, legal);
+
+        // a quiet transposition table move - everything at once
+        let tt_move = find(&order, "e1f1");
+        let mut picker =
+            MovePicker::staged(&board, false, Some(tt_move), &heuristics, 0, &NULL_MOVE);
+        assert_eq!(picker.n_generated(), legal.len());
+        assert_eq!(picker.next_move(&board, &heuristics), Some(tt_move));
+    }
+}
@@ -1,63 +1,63 @@
 // file generated by bin/build_openings.rs
 // copy to src/openings.rs
 
-pub fn library_moves(hash: u64) -> &'static [(u8, u8)] {
+pub fn library_moves(hash: u64) -> &'static [&'static str] {
     match hash {
-        10078298752136636340 => &[(16, 9)][0..1],
-        10802856595343226776 => &[(28, 35), (55, 38)][0..2],
-        11632981255104606137 => &[(15, 21)][0..1],
-        12470772725870396553 => &[(33, 35)][0..1],
-        12531703590984499969 => &[(42, 27)][0..1],
-        13540566761283390643 => &[(24, 8)][0..1],
-        1359073566502882138 => &[(54, 53)][0..1],
-        13928791362737381756 => &[(9, 10)][0..1],
-        14016742330734493649 => &[(33, 35)][0..1],
-        14482118118218993800 => &[(48, 42), (8, 18)][0..2],
-        14573670214957680066 => &[(40, 12)][0..1],
-        15034222533558770062 => &[(31, 15)][0..1],
-        15571283766177242844 => &[(23, 30)][0..1],
-        15577698855300172983 => &[(41, 43)][0..1],
-        15587326414013975935 => &[(8, 18)][0..1],
-        15667798895124951833 => &[(38, 37)][0..1],
-        16060907301186280109 => &[(48, 42)][0..1],
-        161821252619273486 => &[(8, 18)][0..1],
-        16233530819831784508 => &[(30, 28), (30, 29), (46, 45), (46, 44)][0..4],
-        1629522765967710817 => &[(48, 42)][0..1],
-        16569238076636508410 => &[(38, 45)][0..1],
-        17011159949645719075 => &[(38, 37)][0..1],
-        17105686946156605549 => &[(23, 30), (21, 27)][0..2],
-        17211847707048825775 => &[(23, 51)][0..1],
-        17291012998105394234 => &[(47, 54)][0..1],
-        17468592054983458198 => &[(55, 45)][0..1],
-        177884825819797165 => &[(55, 45), (38, 37)][0..2],
-        17925975674533370250 => &[(15, 21)][0..1],
-        18050173920867617984 => &[(38, 36)][0..1],
-        1845831231994452581 => &[(33, 35)][0..1],
-        2051781044825688545 => &[(48, 42)][0..1],
-        2085950642441868613 => &[(23, 30)][0..1],
-        2120399816775142532 => &[(16, 52)][0..1],
-        236865121867540683 => &[(8, 18)][0..1],
-        2509673666662158387 => &[(38, 36), (15, 21)][0..2],
-        2601436880502409817 => &[(23, 30)][0..1],
-        2826013802788223811 => &[(62, 61)][0..1],
-        3233943475377131359 => &[(41, 42)][0..1],
-        3356220473555528215 => &[(18, 35)][0..1],
-        3511452752521697606 => &[(15, 21)][0..1],
-        4099881402534535052 => &[(38, 36)][0..1],
-        4206210335344052934 => &[(36, 27)][0..1],
-        4266406807000549200 => &[(32, 41)][0..1],
-        452877241952945758 => &[(41, 43)][0..1],
-        4595964136099718789 => &[(52, 59), (52, 45)][0..2],
-        4689089225338114103 => &[(15, 21)][0..1],
-        5185070623042535677 => &[(54, 52)][0..1],
-        5689094771640816172 => &[(44, 35)][0..1],
-        5786999119951919537 => &[(47, 20)][0..1],
-        6540697459161938644 => &[(30, 29)][0..1],
-        7000038089453967074 => &[(16, 24)][0..1],
-        7801408666595483665 => &[(59, 50)][0..1],
-        8037720671494215621 => &[(33, 35)][0..1],
-        8483139936063314042 => &[(25, 27), (33, 35)][0..2],
-        9557432599232540733 => &[(30, 29)][0..1],
-        _ => &[][0..0],
+        92142410539397042 => &["c8b7"],
+        385106762548848838 => &["c8f5"],
+        534542469391376362 => &["f8b4"],
+        594343510381699192 => &["e7e5", "e7e6", "c7c6", "c7c5"],
+        685936531963258618 => &["b1c3"],
+        751169762087023457 => &["b7b6"],
+        1102117361705194729 => &["b1c3"],
+        1121753843365095543 => &["b1c3"],
+        1877549564078546544 => &["e1g1"],
+        2329948284955221571 => &["c2c3"],
+        2579338529374142892 => &["d2d4"],
+        3534667775158131192 => &["b8c6", "d7d6"],
+        3644446438643484183 => &["d7d6"],
+        3865521890261207594 => &["g8f6"],
+        4050641105829071433 => &["d7d5", "g8f6"],
+        4635480505000946463 => &["d7d5"],
+        5087913878047480076 => &["d7d5"],
+        5220827108695089180 => &["c1g5"],
+        6412665403824449664 => &["g1f3"],
+        7024383807976714103 => &["f1b5"],
+        7484815818452333638 => &["g8f6"],
+        7933710964774917058 => &["d7d6"],
+        8008876724912194523 => &["b1c3", "g1f3"],
+        8552394307350888601 => &["g1f3"],
+        8586620169150231999 => &["c2c4"],
+        8997131244492213142 => &["d2d4"],
+        9424694478594041280 => &["a7a6"],
+        9561361230653139245 => &["f8e7", "f6e4"],
+        9583534993964074923 => &["e5d4", "b8d7"],
+        10491021651199035188 => &["e7e6"],
+        10506067300856391037 => &["b5a4", "b5c6"],
+        10511374099400490173 => &["g1f3"],
+        10756356682887046521 => &["g8f6"],
+        11230271231941739398 => &["f1g2"],
+        11378094786676241656 => &["f8e7"],
+        11457980346299812942 => &["e2e4", "d2d4"],
+        11795542715448066815 => &["f3d4"],
+        11958931746390441782 => &["b7b5"],
+        12939487419061385577 => &["d7c6"],
+        12994056433974234046 => &["f1e1"],
+        13311509665571019508 => &["d5e4"],
+        13442868463649864637 => &["d1c2"],
+        13723758624886663399 => &["g8f6"],
+        14910449756333751852 => &["c2c4"],
+        15377285159209867617 => &["e8g8"],
+        15674780783571441011 => &["c3e4"],
+        15676417908341163324 => &["a4b3"],
+        15845937031379471761 => &["c5d4"],
+        15999296156190934914 => &["b8c6"],
+        16262443570741305099 => &["d2d4"],
+        16672501748807920920 => &["d2d4"],
+        17375981669392788632 => &["e7e6"],
+        17422676011132842165 => &["g2g3"],
+        17619783677577478400 => &["f8e7"],
+        17624389729560480775 => &["f8e7"],
+        _ => &[],
     }
 }
@@ -0,0 +1,473 @@
+// Portable Game Notation - https://www.saremo.com/pgn/pgn_spec.txt
+//
+// PgnReader streams games from any BufRead - one game is read and parsed at a time.
+// Movetext may span lines and carry {comments}, ; comments, NAGs ($1 or !?) and
+// nested (variations). Moves are parsed with the SAN parser, so sloppy SAN is fine.
+
+use crate::mgen::{Board, Move};
+use crate::san::{self, SanError};
+use flate2::read::MultiGzDecoder;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+
+#[derive(Debug)]
+pub enum PgnError {
+    Io(io::Error),
+    /// Malformed tag pair or movetext
+    Syntax(String),
+    /// The FEN tag does not parse
+    Fen(String),
+    /// A move that is not legal in its position
+    Move(SanError),
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PgnError::Io(e) => write!(f, "{e}"),
+            PgnError::Syntax(s) => write!(f, "PGN syntax: {s}"),
+            PgnError::Fen(s) => write!(f, "Bad FEN tag: {s}"),
+            PgnError::Move(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for PgnError {}
+
+impl From<io::Error> for PgnError {
+    fn from(e: io::Error) -> Self {
+        PgnError::Io(e)
+    }
+}
+
+/// A move with its annotations and the alternatives to it
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgnMove {
+    pub mv: Move,
+    /// as written, annotation marks like "!?" stripped
+    pub san: String,
+    pub nags: Vec<u8>,
+    /// comments following the move
+    pub comments: Vec<String>,
+    /// lines played instead of this move
+    pub variations: Vec<Variation>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Variation {
+    /// comments before the first move
+    pub comments: Vec<String>,
+    pub moves: Vec<PgnMove>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PgnGame {
+    pub tags: HashMap<String, String>,
+    /// comments before the first move
+    pub comments: Vec<String>,
+    pub moves: Vec<PgnMove>,
+    /// game termination marker - "1-0", "0-1", "1/2-1/2" or "*"
+    pub result: String,
+}
+
+impl PgnGame {
+    pub fn tag(&self, key: &str) -> Option<&str> {
+        self.tags.get(key).map(String::as_str)
+    }
+
+    /// Position the game starts from - the FEN tag or the standard start position
+    pub fn start(&self) -> Board {
+        match self.tag("FEN") {
+            Some(fen) => Board::from_fen(fen).expect("FEN tag checked when read"),
+            None => Board::default(),
+        }
+    }
+
+    pub fn mainline(&self) -> impl Iterator<Item = Move> + '_ {
+        self.moves.iter().map(|m| m.mv)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Token<'a> {
+    Move(&'a str, Option<u8>),
+    Nag(u8),
+    Comment(&'a str),
+    Open,
+    Close,
+    Result(&'a str),
+}
+
+// "12." and "12..." in front of a move
+fn strip_move_number(w: &str) -> &str {
+    let rest = w.trim_start_matches(|c: char| c.is_ascii_digit());
+    if rest.len() < w.len() && rest.starts_with('.') {
+        rest.trim_start_matches('.')
+    } else {
+        w
+    }
+}
+
+fn tokens(text: &str) -> Result<Vec<Token<'_>>, PgnError> {
+    let b = text.as_bytes();
+    let mut l = Vec::new();
+    let mut i = 0;
+    while i < b.len() {
+        let start = i;
+        match b[i] {
+            c if c.is_ascii_whitespace() => i += 1,
+            b'{' => {
+                let Some(n) = text[i..].find('}') else {
+                    return Err(PgnError::Syntax("unterminated comment".to_string()));
+                };
+                l.push(Token::Comment(text[i + 1..i + n].trim()));
+                i += n + 1;
+            }
+            b';' => {
+                let n = text[i..].find('\n').unwrap_or(text.len() - i);
+                l.push(Token::Comment(text[i + 1..i + n].trim()));
+                i += n;
+            }
+            b'(' => {
+                l.push(Token::Open);
+                i += 1;
+            }
+            b')' => {
+                l.push(Token::Close);
+                i += 1;
+            }
+            b'$' => {
+                i += 1;
+                while i < b.len() && b[i].is_ascii_digit() {
+                    i += 1;
+                }
+                match text[start + 1..i].parse() {
+                    Ok(n) => l.push(Token::Nag(n)),
+                    Err(_) => return Err(PgnError::Syntax(format!("bad NAG {}", &text[start..i]))),
+                }
+            }
+            _ => {
+                while i < b.len() && !b[i].is_ascii_whitespace() && !b"{}();$".contains(&b[i]) {
+                    i += 1;
+                }
+                if i == start {
+                    return Err(PgnError::Syntax("unexpected '}'".to_string()));
+                }
+                let w = &text[start..i];
+                if matches!(w, "1-0" | "0-1" | "1/2-1/2" | "*") {
+                    l.push(Token::Result(w));
+                    continue;
+                }
+                let w = strip_move_number(w);
+                if w.is_empty() {
+                    continue;
+                }
+                let san = w.trim_end_matches(['!', '?']);
+                let nag = match &w[san.len()..] {
+                    "!" => Some(1),
+                    "?" => Some(2),
+                    "!!" => Some(3),
+                    "??" => Some(4),
+                    "!?" => Some(5),
+                    "?!" => Some(6),
+                    _ => None,
+                };
+                // a glyph written apart from its move - "e4 !"
+                match (san, nag) {
+                    ("", Some(n)) => l.push(Token::Nag(n)),
+                    ("", None) => return Err(PgnError::Syntax(format!("bad annotation {w}"))),
+                    _ => l.push(Token::Move(san, nag)),
+                }
+            }
+        }
+    }
+    Ok(l)
+}
+
+// [Key "Value"]
+fn parse_tag(line: &str) -> Option<(String, String)> {
+    let inner = line.trim().strip_prefix('[')?.strip_suffix(']')?.trim();
+    let (key, value) = inner.split_once(char::is_whitespace)?;
+    let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+    Some((
+        key.to_string(),
+        value.replace("\\\"", "\"").replace("\\\\", "\\"),
+    ))
+}
+
+// one line of play from tokens[*i..] - up to the closing bracket of a variation, or
+// the result of the mainline. The board is left as it was found
+fn parse_line<'a>(
+    board: &mut Board,
+    tokens: &[Token<'a>],
+    i: &mut usize,
+    depth: usize,
+    result: &mut &'a str,
+) -> Result<Variation, PgnError> {
+    let mut line = Variation::default();
+    let mut closed = false;
+    while let Some(&token) = tokens.get(*i) {
+        *i += 1;
+        match token {
+            Token::Move(s, nag) => {
+                let moves = board.legal_moves(false);
+                let m = san::parse(board, &moves, s).map_err(PgnError::Move)?;
+                board.update(&m);
+                line.moves.push(PgnMove {
+                    mv: m,
+                    san: s.to_string(),
+                    nags: nag.into_iter().collect(),
+                    comments: vec![],
+                    variations: vec![],
+                });
+            }
+            Token::Nag(n) => {
+                if let Some(last) = line.moves.last_mut() {
+                    last.nags.push(n)
+                }
+            }
+            Token::Comment(c) => match line.moves.last_mut() {
+                Some(last) => last.comments.push(c.to_string()),
+                None => line.comments.push(c.to_string()),
+            },
+            Token::Open => {
+                let Some(last) = line.moves.last_mut() else {
+                    return Err(PgnError::Syntax("variation before any move".to_string()));
+                };
+                // an alternative to the last move
+                board.backdate(&last.mv);
+                let variation = parse_line(board, tokens, i, depth + 1, result)?;
+                board.update(&last.mv);
+                last.variations.push(variation);
+            }
+            Token::Close if depth > 0 => {
+                closed = true;
+                break;
+            }
+            Token::Close => return Err(PgnError::Syntax("unmatched ')'".to_string())),
+            Token::Result(r) if depth == 0 => {
+                *result = r;
+                break;
+            }
+            Token::Result(r) => return Err(PgnError::Syntax(format!("{r} in a variation"))),
+        }
+    }
+    if depth > 0 && !closed {
+        return Err(PgnError::Syntax("unterminated variation".to_string()));
+    }
+    for m in line.moves.iter().rev() {
+        board.backdate(&m.mv);
+    }
+    Ok(line)
+}
+
+fn parse_game(tag_lines: &[String], movetext: &str) -> Result<PgnGame, PgnError> {
+    let mut tags = HashMap::new();
+    for line in tag_lines {
+        let Some((key, value)) = parse_tag(line) else {
+            return Err(PgnError::Syntax(format!("bad tag {line}")));
+        };
+        tags.insert(key, value);
+    }
+    let mut board = match tags.get("FEN") {
+        Some(fen) => Board::from_fen(fen).map_err(PgnError::Fen)?,
+        None => Board::default(),
+    };
+
+    let tokens = tokens(movetext)?;
+    let mut result = "*";
+    let line = parse_line(&mut board, &tokens, &mut 0, 0, &mut result)?;
+    Ok(PgnGame {
+        tags,
+        comments: line.comments,
+        moves: line.moves,
+        result: result.to_string(),
+    })
+}
+
+/// Iterator over the games of a PGN stream. A game that does not parse is
+/// returned as an error - reading goes on with the next game
+pub struct PgnReader<R> {
+    lines: io::Lines<R>,
+    next_tag: Option<String>, // 1st tag line of the next game
+}
+
+impl PgnReader<Box<dyn BufRead>> {
+    /// Games from a .pgn file - gzip compressed or plain
+    pub fn open(path: &str) -> io::Result<Self> {
+        let mut file = BufReader::new(File::open(path)?);
+        let gz = file.fill_buf()?.starts_with(&[0x1f, 0x8b]);
+        let reader: Box<dyn BufRead> = if gz {
+            Box::new(BufReader::new(MultiGzDecoder::new(file)))
+        } else {
+            Box::new(file)
+        };
+        Ok(PgnReader::new(reader))
+    }
+}
+
+impl<R: BufRead> PgnReader<R> {
+    pub fn new(reader: R) -> Self {
+        PgnReader {
+            lines: reader.lines(),
+            next_tag: None,
+        }
+    }
+
+    // tag lines and movetext of the next game
+    fn read_game(&mut self) -> io::Result<Option<(Vec<String>, String)>> {
+        let mut tags = Vec::new();
+        let mut movetext = String::new();
+        let mut in_comment = false; // a {comment} spanning lines
+        loop {
+            let line = match self.next_tag.take() {
+                Some(line) => line,
+                None => match self.lines.next() {
+                    Some(line) => line?,
+                    None => break,
+                },
+            };
+            let line = line.trim_start_matches('\u{feff}');
+            if !in_comment {
+                if line.starts_with('%') {
+                    continue; // escape mechanism
+                }
+                if line.trim_start().starts_with('[') {
+                    if !movetext.trim().is_empty() {
+                        self.next_tag = Some(line.to_string());
+                        break;
+                    }
+                    tags.push(line.to_string());
+                    continue;
+                }
+            }
+            for c in line.chars() {
+                match c {
+                    '{' if !in_comment => in_comment = true,
+                    '}' if in_comment => in_comment = false,
+                    ';' if !in_comment => break,
+                    _ => {}
+                }
+            }
+            movetext.push_str(line);
+            movetext.push('\n');
+        }
+        if tags.is_empty() && movetext.trim().is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some((tags, movetext)))
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for PgnReader<R> {
+    type Item = Result<PgnGame, PgnError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.read_game() {
+            Ok(Some((tags, movetext))) => Some(parse_game(&tags, &movetext)),
+            Ok(None) => None,
+            Err(e) => Some(Err(PgnError::Io(e))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+
+    const PGN: &str = r#"[Event "Casual \"blitz\""]
+[White "A"]
+[Black "B"]
+[Result "1-0"]
+
+{Opening} 1. e4 e5 2. Nf3 $1 Nc6 (2... d6 {Philidor} 3. d4 (3. Bc4
+Be7) exd4) 3.Bb5!? a6 ; the Morphy defence
+4. Ba4 {spans
+two lines} Nf6 5. O-O 1-0
+
+[Event "Second"]
+[SetUp "1"]
+[FEN "4k3/1P6/8/8/8/8/8/4K3 w - - 0 1"]
+
+1. b8=N Kf7 *
+"#;
+
+    #[test]
+    fn test_reader() {
+        let games: Vec<PgnGame> = PgnReader::new(PGN.as_bytes())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(games.len(), 2);
+
+        let g = &games[0];
+        assert_eq!(g.tag("Event"), Some("Casual \"blitz\""));
+        assert_eq!(g.result, "1-0");
+        assert_eq!(g.comments, ["Opening"]);
+        let labels: Vec<&str> = g.moves.iter().map(|m| m.san.as_str()).collect();
+        assert_eq!(
+            labels,
+            ["e4", "e5", "Nf3", "Nc6", "Bb5", "a6", "Ba4", "Nf6", "O-O"]
+        );
+        assert_eq!(g.moves[2].nags, [1]);
+        assert_eq!(g.moves[4].nags, [5]);
+        assert_eq!(g.moves[5].comments, ["the Morphy defence"]);
+        assert_eq!(g.moves[6].comments, ["spans\ntwo lines"]);
+
+        // 2... d6 instead of 2... Nc6 - with a variation of its own
+        let v = &g.moves[3].variations[0];
+        let labels: Vec<&str> = v.moves.iter().map(|m| m.san.as_str()).collect();
+        assert_eq!(labels, ["d6", "d4", "exd4"]);
+        assert_eq!(v.moves[0].comments, ["Philidor"]);
+        let vv = &v.moves[1].variations[0];
+        assert_eq!(vv.moves.len(), 2);
+
+        let mut game = crate::Game::new(g.start());
+        for m in g.mainline() {
+            game.make_move(m);
+        }
+        let history = game.history();
+        let labels: Vec<&str> = history.iter().map(|(_, l)| l.as_str()).collect();
+        assert_eq!(labels[8], "O-O");
+
+        let g = &games[1];
+        assert_eq!(g.result, "*");
+        assert!(g.moves[0].mv.transform());
+        assert_eq!(g.start().to_fen(), "4k3/1P6/8/8/8/8/8/4K3 w - - 0 1");
+    }
+
+    #[test]
+    fn test_errors() {
+        let pgn = "[Event \"bad\"]\n\n1. e4 e5 2. Ke3 *\n\n[Event \"ok\"]\n\n1. d4 *\n\n\
+                   [Event \"unmatched\"]\n\n1. d4 (1. e4 *\n\n\
+                   [Event \"x\"]\n\n1. e4 } e5 *\n\n\
+                   [Event \"glyphs\"]\n\n1. e4 ! e5 ?! $14 *\n";
+        let games: Vec<_> = PgnReader::new(pgn.as_bytes()).collect();
+        assert_eq!(games.len(), 5);
+        assert!(matches!(&games[0], Err(PgnError::Move(SanError::Illegal(s))) if s == "Ke3"));
+        assert_eq!(games[1].as_ref().unwrap().moves.len(), 1);
+        assert!(matches!(games[2], Err(PgnError::Syntax(_))));
+        assert!(matches!(games[3], Err(PgnError::Syntax(_))));
+        let g = games[4].as_ref().unwrap();
+        assert_eq!(
+            (g.moves[0].nags.as_slice(), g.moves[1].nags.as_slice()),
+            (&[1][..], &[6, 14][..])
+        );
+    }
+
+    #[test]
+    fn test_gz() {
+        let path = std::env::temp_dir().join(format!("mateus_test_{}.pgn.gz", std::process::id()));
+        let mut enc = GzEncoder::new(File::create(&path).unwrap(), Compression::default());
+        enc.write_all(PGN.as_bytes()).unwrap();
+        enc.finish().unwrap();
+        let n = PgnReader::open(path.to_str().unwrap()).unwrap().count();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(n, 2);
+    }
+}
@@ -0,0 +1,276 @@
+// Polyglot opening books - http://hgm.nubati.net/book_format.html
+//
+// A book is a sorted list of 16 byte entries (big endian): position key u64, move u16,
+// weight u16, learn u32. Positions are keyed by the standard Polyglot Zobrist hash,
+// which is built from the fixed table of 781 "Random64" constants in random64_generated.rs.
+
+use crate::Game;
+use crate::mgen::{Board, CASTLE_B_LONG, CASTLE_B_SHORT, CASTLE_W_LONG, CASTLE_W_SHORT, Move};
+use crate::random64_generated::RANDOM64;
+use crate::val::*;
+use std::fs;
+
+const CASTLE_OFFSET: usize = 768;
+const EN_PASSANT_OFFSET: usize = 772;
+const TURN_OFFSET: usize = 780;
+const ENTRY_SIZE: usize = 16;
+
+// polyglot square index: 8 * row + file, a1 = 0, h8 = 63
+#[inline]
+fn pg_square(i: usize) -> usize {
+    8 * (i % 8) + (7 - i / 8)
+}
+
+// inverse of pg_square
+#[inline]
+fn our_square(file: usize, row: usize) -> u8 {
+    ((7 - file) * 8 + row) as u8
+}
+
+/// Polyglot key of the position
+pub fn key(board: &Board) -> u64 {
+    let mut key = 0;
+    for (i, p) in board.into_iter().enumerate() {
+        let kind = match p.kind() {
+            PAWN => 0,
+            KNIGHT => 1,
+            BISHOP => 2,
+            ROOK => 3,
+            QUEEN => 4,
+            KING => 5,
+            _ => continue,
+        };
+        let kind_of_piece = 2 * kind + p.is_white() as usize;
+        key ^= RANDOM64[64 * kind_of_piece + pg_square(i)];
+    }
+
+    for (i, c) in [CASTLE_W_SHORT, CASTLE_W_LONG, CASTLE_B_SHORT, CASTLE_B_LONG]
+        .into_iter()
+        .enumerate()
+    {
+        if board.can_castle & c != 0 {
+            key ^= RANDOM64[CASTLE_OFFSET + i];
+        }
+    }
+
+    // en passant only counts if a pawn of the side to move can make the capture
+    let ep = board.en_passant_sq() as usize;
+    if ep != 0 {
+        let (file, row) = (7 - ep / 8, ep % 8);
+        let (pawn, row) = if board.colour.is_white() {
+            (WPAWN, row - 1)
+        } else {
+            (BPAWN, row + 1)
+        };
+        let can_capture = [file.wrapping_sub(1), file + 1]
+            .into_iter()
+            .any(|f| f < 8 && board[our_square(f, row) as usize] == pawn);
+        if can_capture {
+            key ^= RANDOM64[EN_PASSANT_OFFSET + file];
+        }
+    }
+
+    if board.colour.is_white() {
+        key ^= RANDOM64[TURN_OFFSET];
+    }
+    key
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BookEntry {
+    pub key: u64,
+    pub mv: u16,
+    pub weight: u16,
+    pub learn: u32,
+}
+
+impl BookEntry {
+    fn from_bytes(b: &[u8]) -> BookEntry {
+        BookEntry {
+            key: u64::from_be_bytes(b[0..8].try_into().unwrap()),
+            mv: u16::from_be_bytes(b[8..10].try_into().unwrap()),
+            weight: u16::from_be_bytes(b[10..12].try_into().unwrap()),
+            learn: u32::from_be_bytes(b[12..16].try_into().unwrap()),
+        }
+    }
+
+    // (frm, to, promotion kind) - castling is encoded as king takes own rook
+    fn decode(&self) -> (u8, u8, Option<u8>) {
+        let field = |shift: u16| (self.mv >> shift & 7) as usize;
+        let frm = our_square(field(6), field(9));
+        let to = our_square(field(0), field(3));
+        let promotion = match field(12) {
+            1 => Some(KNIGHT),
+            2 => Some(BISHOP),
+            3 => Some(ROOK),
+            4 => Some(QUEEN),
+            _ => None,
+        };
+        (frm, to, promotion)
+    }
+}
+
+/// Polyglot opening book - loaded into memory
+pub struct Book {
+    entries: Vec<BookEntry>,
+}
+
+impl Book {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Book, String> {
+        if !bytes.len().is_multiple_of(ENTRY_SIZE) {
+            return Err("Polyglot book size is not a multiple of 16 bytes".into());
+        }
+        let mut entries: Vec<BookEntry> = bytes
+            .chunks_exact(ENTRY_SIZE)
+            .map(BookEntry::from_bytes)
+            .collect();
+        // books should be sorted by key already - stable sort keeps their move order
+        entries.sort_by_key(|e| e.key);
+        Ok(Book { entries })
+    }
+
+    pub fn open(path: &str) -> Result<Book, String> {
+        let bytes = fs::read(path).map_err(|e| format!("{path}: {e}"))?;
+        Book::from_bytes(&bytes)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Book entries for a position
+    pub fn entries(&self, board: &Board) -> &[BookEntry] {
+        let key = key(board);
+        let i = self.entries.partition_point(|e| e.key < key);
+        let n = self.entries[i..].partition_point(|e| e.key == key);
+        &self.entries[i..i + n]
+    }
+
+    /// Legal book moves with their weights
+    pub fn moves(&self, game: &mut Game) -> Vec<(Move, u16)> {
+        let entries = self.entries(&game.board);
+        if entries.is_empty() {
+            return vec![];
+        }
+        let legal = game.legal_moves();
+        entries
+            .iter()
+            .filter_map(|e| {
+                // castling is king takes rook (e1h1) - as stored in Move
+                let (frm, to, promotion) = e.decode();
+                legal
+                    .iter()
+                    .find(|m| {
+                        (m.frm(), m.to()) == (frm, to)
+                            && match promotion {
+                                Some(k) => m.transform() && m.promote_kind() == k,
+                                None => !m.transform(),
+                            }
+                    })
+                    .map(|m| (*m, e.weight))
+            })
+            .collect()
+    }
+
+    /// Random book move - chosen with probability proportional to its weight
+    pub fn pick(&self, game: &mut Game) -> Option<Move> {
+        let moves = self.moves(game);
+        let total: u32 = moves.iter().map(|(_, w)| *w as u32).sum();
+        if total == 0 {
+            return moves.first().map(|(m, _)| *m);
+        }
+        let mut r = rand::random_range(0..total);
+        for (m, w) in moves {
+            if r < w as u32 {
+                return Some(m);
+            }
+            r -= w as u32;
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::misc::str2move;
+
+    fn entry(key: u64, mv: &str, weight: u16) -> Vec<u8> {
+        let (frm, to) = str2move(mv).unwrap();
+        let sq = |i: u8| (i as usize % 8, 7 - i as usize / 8); // (row, file)
+        let ((fr, ff), (tr, tf)) = (sq(frm), sq(to));
+        let mv = (tf | tr << 3 | ff << 6 | fr << 9) as u16;
+        let mut v = key.to_be_bytes().to_vec();
+        v.extend(mv.to_be_bytes());
+        v.extend(weight.to_be_bytes());
+        v.extend(0u32.to_be_bytes());
+        v
+    }
+
+    #[test]
+    fn test_key() {
+        // reference keys from the book_format.html spec
+        let play = |moves: &str| {
+            let mut board = Board::default();
+            for mv in moves.split_whitespace() {
+                board.update(&Move::from_uci(&board, mv).unwrap());
+            }
+            key(&board)
+        };
+        for (moves, k) in [
+            ("", 0x463b96181691fc9c),
+            ("e2e4", 0x823c9b50fd114196),
+            ("e2e4 d7d5", 0x0756b94461c50fb0),
+            ("e2e4 d7d5 e4e5", 0x662fafb965db29d4),
+            ("e2e4 d7d5 e4e5 f7f5", 0x22a48b5a8e47ff78),
+            ("e2e4 d7d5 e4e5 f7f5 e1e2", 0x652a607ca3f242c1),
+            ("e2e4 d7d5 e4e5 f7f5 e1e2 e8f7", 0x00fdd303c946bdd9),
+            ("a2a4 b7b5 h2h4 b5b4 c2c4", 0x3c8123ea7b067637),
+            ("a2a4 b7b5 h2h4 b5b4 c2c4 b4c3 a1a3", 0x5c3f9b829b279560),
+        ] {
+            assert_eq!(play(moves), k, "{moves}");
+        }
+
+        // en passant square only hashed when the capture is possible
+        let ep = Board::from_fen("4k3/8/8/8/p3P3/8/8/4K3 b - e3 0 1").unwrap();
+        let no_ep = Board::from_fen("4k3/8/8/8/p3P3/8/8/4K3 b - - 0 1").unwrap();
+        assert_eq!(key(&ep), key(&no_ep));
+    }
+
+    #[test]
+    fn test_book() {
+        let start = key(&Board::default());
+        let castle = Board::from_fen("4k3/8/8/8/8/8/8/4K2R w K - 0 1").unwrap();
+        let castle_key = key(&castle);
+
+        let mut bytes = vec![];
+        bytes.extend(entry(start, "e2e4", 3));
+        bytes.extend(entry(start, "d2d4", 1));
+        bytes.extend(entry(start, "e2e5", 5)); // illegal - skipped
+        bytes.extend(entry(castle_key, "e1h1", 1));
+        let book = Book::from_bytes(&bytes).unwrap();
+        assert_eq!(book.len(), 4);
+
+        let mut game = Game::default();
+        let moves: Vec<String> = book
+            .moves(&mut game)
+            .iter()
+            .map(|(m, w)| format!("{m}/{w}"))
+            .collect();
+        assert_eq!(moves, ["e2e4/3", "d2d4/1"]);
+        let m = book.pick(&mut game).unwrap();
+        assert!(["e2e4", "d2d4"].contains(&m.to_string().as_str()));
+
+        let mut game = Game::new(castle);
+        let moves = book.moves(&mut game);
+        assert_eq!(moves.len(), 1);
+        assert!(moves[0].0.castle() && moves[0].0.to_string() == "e1g1");
+
+        game.make_move(moves[0].0);
+        assert!(book.pick(&mut game).is_none());
+    }
+}
@@ -0,0 +1,292 @@
+// Standard Algebraic Notation input - https://en.wikipedia.org/wiki/Algebraic_notation_(chess)
+//
+// Besides strict SAN ("Nbd7", "exd8=Q+", "O-O") the parser accepts the usual sloppy
+// forms: missing or extra 'x', "0-0", long algebraic ("Ng1f3", "e2-e4"), promotions
+// without '=' or in lower case ("e8q"), a missing promotion piece (queen), lower case
+// piece letters and annotations like "!?" or "e.p.".
+
+use crate::mgen::{Board, Move};
+use crate::misc::parse_chess_coord;
+use crate::val::*;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SanError {
+    /// Not a move in algebraic notation
+    Syntax(String),
+    /// Well formed, but no legal move matches
+    Illegal(String),
+    /// More than one legal move matches
+    Ambiguous(String),
+}
+
+impl fmt::Display for SanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SanError::Syntax(s) => write!(f, "Invalid move: {s}"),
+            SanError::Illegal(s) => write!(f, "Illegal move: {s}"),
+            SanError::Ambiguous(s) => write!(f, "Ambiguous move: {s}"),
+        }
+    }
+}
+
+impl std::error::Error for SanError {}
+
+// what the text says about the move
+#[derive(Debug, PartialEq)]
+enum San {
+    Castle {
+        long: bool,
+    },
+    Piece {
+        kind: u8,
+        file: Option<u8>,
+        rank: Option<u8>,
+        to: u8,
+        promote: Option<u8>,
+    },
+}
+
+fn piece_kind(c: char) -> Option<u8> {
+    match c.to_ascii_uppercase() {
+        'P' => Some(PAWN),
+        'N' => Some(KNIGHT),
+        'B' => Some(BISHOP),
+        'R' => Some(ROOK),
+        'Q' => Some(QUEEN),
+        'K' => Some(KING),
+        _ => None,
+    }
+}
+
+// drop check/mate marks, annotations and "e.p."
+fn strip_suffix(s: &str) -> &str {
+    let s = s.trim();
+    let s = s.strip_suffix("e.p.").unwrap_or(s).trim_end();
+    s.trim_end_matches(['+', '#', '!', '?'])
+}
+
+// `bishop` decides whether a leading 'b' is a piece or the b-file
+fn lex(s: &str, bishop: bool) -> Option<San> {
+    let castle: String = s
+        .chars()
+        .filter(|&c| c != '-')
+        .map(|c| {
+            if c == '0' {
+                'O'
+            } else {
+                c.to_ascii_uppercase()
+            }
+        })
+        .collect();
+    match castle.as_str() {
+        "OO" => return Some(San::Castle { long: false }),
+        "OOO" => return Some(San::Castle { long: true }),
+        _ => {}
+    }
+
+    let mut chars: Vec<char> = s
+        .chars()
+        .filter(|c| !matches!(c, 'x' | 'X' | ':' | '-'))
+        .collect();
+
+    // promotion: a trailing piece letter, maybe behind '=', '/' or in brackets
+    let mut promote = None;
+    if chars.last() == Some(&')') {
+        chars.pop();
+    }
+    if let Some(&c) = chars.last()
+        && !c.is_ascii_digit()
+    {
+        promote = Some(piece_kind(c).filter(|&k| k != PAWN && k != KING)?);
+        chars.pop();
+        if matches!(chars.last(), Some('=' | '/' | '(')) {
+            chars.pop();
+        }
+    }
+
+    let kind = match chars.first() {
+        Some(&c) if c.is_ascii_uppercase() || (c == 'b' && bishop) => {
+            chars.remove(0);
+            piece_kind(c)?
+        }
+        Some(&c) if matches!(c, 'n' | 'r' | 'q' | 'k') => {
+            chars.remove(0);
+            piece_kind(c)?
+        }
+        _ => PAWN,
+    };
+
+    // [file][rank] square
+    if !(2..=4).contains(&chars.len()) {
+        return None;
+    }
+    let (hint, sq) = chars.split_at(chars.len() - 2);
+    let to = parse_chess_coord(&sq.iter().collect::<String>())?;
+    let (mut file, mut rank) = (None, None);
+    for &c in hint {
+        match c {
+            'a'..='h' if file.is_none() && rank.is_none() => file = Some(c as u8 - b'a'),
+            '1'..='8' if rank.is_none() => rank = Some(c as u8 - b'1'),
+            _ => return None,
+        }
+    }
+    Some(San::Piece {
+        kind,
+        file,
+        rank,
+        to,
+        promote,
+    })
+}
+
+fn matches(board: &Board, m: &Move, san: &San) -> bool {
+    match *san {
+        San::Castle { long } => m.castle() && (m.to() > m.frm()) == long,
+        San::Piece {
+            kind,
+            file,
+            rank,
+            to,
+            promote,
+        } => {
+            let frm = m.frm();
+            !m.castle()
+                && m.to() == to
+                && board[frm as usize].kind() == kind
+                && file.is_none_or(|f| 7 - frm / 8 == f)
+                && rank.is_none_or(|r| frm % 8 == r)
+                && match promote {
+                    Some(k) => m.transform() && m.promote_kind() == k,
+                    None => !m.transform() || m.promote_kind() == QUEEN,
+                }
+        }
+    }
+}
+
+fn find(board: &Board, moves: &[Move], san: &San, s: &str) -> Result<Move, SanError> {
+    let mut found = moves.iter().filter(|m| matches(board, m, san));
+    match (found.next(), found.next()) {
+        (Some(m), None) => Ok(*m),
+        (None, _) => Err(SanError::Illegal(s.to_string())),
+        _ => Err(SanError::Ambiguous(s.to_string())),
+    }
+}
+
+/// The move in `moves` (the legal moves of `board`) that the SAN text `s` denotes
+pub fn parse(board: &Board, moves: &[Move], s: &str) -> Result<Move, SanError> {
+    let text = strip_suffix(s);
+    let res = match lex(text, false) {
+        Some(san) => find(board, moves, &san, s),
+        None => Err(SanError::Syntax(s.to_string())),
+    };
+    // "bc4" - the b-pawn or, sloppily, a bishop; "bf1c4" only reads as a bishop
+    if res.is_err()
+        && text.starts_with('b')
+        && let Some(san) = lex(text, true)
+    {
+        let bishop = find(board, moves, &san, s);
+        if bishop.is_ok() || matches!(res, Err(SanError::Syntax(_))) {
+            return bishop;
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_fen(fen: &str, s: &str) -> Result<(u8, u8), SanError> {
+        let board = Board::from_fen(fen).unwrap();
+        let moves = board.legal_moves(false);
+        parse(&board, &moves, s).map(|m| (m.frm(), m.king_to()))
+    }
+
+    #[test]
+    fn test_lex() {
+        assert_eq!(lex("O-O-O", false), Some(San::Castle { long: true }));
+        assert_eq!(lex("0-0", false), Some(San::Castle { long: false }));
+        assert_eq!(
+            lex("exd8=Q", false),
+            Some(San::Piece {
+                kind: PAWN,
+                file: Some(4),
+                rank: None,
+                to: parse_chess_coord("d8").unwrap(),
+                promote: Some(QUEEN)
+            })
+        );
+        assert_eq!(lex("Nbd7", false), lex("Nb-d7", false));
+        assert_eq!(lex("e8k", false), None);
+        assert_eq!(lex("Zf3", false), None);
+        assert_eq!(lex("Nf", false), None);
+        assert_eq!(lex("N1bd7", false), None);
+    }
+
+    #[test]
+    fn test_parse() {
+        let sq = |s| parse_chess_coord(s).unwrap();
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert_eq!(parse_fen(start, "e4"), Ok((sq("e2"), sq("e4"))));
+        assert_eq!(parse_fen(start, "e2-e4"), Ok((sq("e2"), sq("e4"))));
+        assert_eq!(parse_fen(start, "Nf3"), Ok((sq("g1"), sq("f3"))));
+        assert_eq!(parse_fen(start, "ng1f3"), Ok((sq("g1"), sq("f3"))));
+        let italian = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3";
+        assert_eq!(parse_fen(italian, "bf1c4"), Ok((sq("f1"), sq("c4"))));
+        assert_eq!(
+            parse_fen(start, "e5"),
+            Err(SanError::Illegal("e5".to_string()))
+        );
+        assert_eq!(
+            parse_fen(start, "hello"),
+            Err(SanError::Syntax("hello".to_string()))
+        );
+
+        // disambiguation by file and rank
+        let fen = "4k3/8/8/8/8/1N6/8/1N2KN2 w - - 0 1";
+        assert_eq!(
+            parse_fen(fen, "Nd2"),
+            Err(SanError::Ambiguous("Nd2".to_string()))
+        );
+        assert_eq!(parse_fen(fen, "Nfd2"), Ok((sq("f1"), sq("d2"))));
+        assert_eq!(parse_fen(fen, "N3d2"), Ok((sq("b3"), sq("d2"))));
+        assert_eq!(parse_fen(fen, "Nb1d2"), Ok((sq("b1"), sq("d2"))));
+
+        // captures, checks, castling
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        assert_eq!(parse_fen(fen, "Nexf7"), Ok((sq("e5"), sq("f7"))));
+        assert_eq!(parse_fen(fen, "Qxf6"), Ok((sq("f3"), sq("f6"))));
+        assert_eq!(parse_fen(fen, "dxe6"), Ok((sq("d5"), sq("e6"))));
+        assert_eq!(parse_fen(fen, "de6"), Ok((sq("d5"), sq("e6"))));
+        assert_eq!(parse_fen(fen, "Bxa6!?"), Ok((sq("e2"), sq("a6"))));
+        assert_eq!(parse_fen(fen, "bxa6"), Ok((sq("e2"), sq("a6"))));
+        assert_eq!(parse_fen(fen, "be2xa6"), Ok((sq("e2"), sq("a6"))));
+        assert_eq!(
+            parse_fen(fen, "be2h5"),
+            Err(SanError::Illegal("be2h5".to_string()))
+        );
+        assert_eq!(parse_fen(fen, "O-O"), Ok((sq("e1"), sq("g1"))));
+        assert_eq!(parse_fen(fen, "0-0-0"), Ok((sq("e1"), sq("c1"))));
+
+        // promotions
+        let fen = "3r3k/4P3/8/8/8/8/8/K7 w - - 0 1";
+        assert_eq!(parse_fen(fen, "exd8=Q+"), Ok((sq("e7"), sq("d8"))));
+        let board = Board::from_fen(fen).unwrap();
+        let moves = board.legal_moves(false);
+        for (s, kind) in [
+            ("e8=N", KNIGHT),
+            ("e8r", ROOK),
+            ("exd8(B)", BISHOP),
+            ("e8", QUEEN),
+        ] {
+            let m = parse(&board, &moves, s).unwrap();
+            assert!(m.transform() && m.promote_kind() == kind, "{s}");
+        }
+        assert!(parse(&board, &moves, "e8=K").is_err());
+
+        // en passant
+        let fen = "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1";
+        assert_eq!(parse_fen(fen, "exd6 e.p."), Ok((sq("e5"), sq("d6"))));
+    }
+}
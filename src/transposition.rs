@@ -1,64 +1,89 @@
-use crate::mgen::{self, Move};
+use crate::INFINITE;
+use crate::mgen::Move;
 use static_assertions::const_assert;
+use std::sync::atomic::{AtomicU8, AtomicU64, Ordering};
 
 // Ensure usize is at least 64-bit at compile time
 const_assert!(std::mem::size_of::<usize>() >= std::mem::size_of::<u64>());
 
-// Table size - examples
-// 2 ^ 20 =    1048576 =   1M
-// 2 ^ 21 =    2097152 =   2M
-// 2 ^ 22 =    4194304 =   4M
-// 2 ^ 23 =    8388608 =   8M
-// 2 ^ 24 =   16777216 =  16M
-// 2 ^ 25 =   33554432 =  34M
-// 2 ^ 26 =   67108864 =  67M
-// 2 ^ 27 =  134217728 = 134M
-// 2 ^ 28 =  268435456 = 268M
-// 2 ^ 29 =  536870912 = 537M
-// 2 ^ 30 = 1073741824 =   1G
-
-const TABLE_SIZE: usize = 1 << 23;
-const MASK: usize = TABLE_SIZE - 1;
+// Table size - examples (entries are 16 bytes)
+// 2 ^ 20 =    1048576 =   1M entries =   16 MB
+// 2 ^ 23 =    8388608 =   8M entries =  128 MB
+// 2 ^ 26 =   67108864 =  67M entries =    1 GB
+// 2 ^ 30 = 1073741824 =   1G entries =   16 GB
+
+/// Default table size in MB
+pub const DEFAULT_HASH_MB: usize = 128;
+// entries are grouped in buckets: a depth-preferred slot and an always-replace slot
+const BUCKET_SIZE: usize = 2;
+// scores beyond this are mates
+const MATE: i16 = INFINITE - 1000;
+
+/// How a stored score relates to the value of the position
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Bound {
+    Upper,
+    Lower,
+    Exact,
+}
+
+impl Bound {
+    /// Bound of a score searched with the window (alpha, beta)
+    pub fn new(score: i16, alpha: i16, beta: i16) -> Bound {
+        if score <= alpha {
+            Bound::Upper
+        } else if score >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        }
+    }
+}
+
+// Packed into 64 bits: 3 x 16 bits (depth, score, move) + 2 x 8 bits (bound, generation).
+// Mate scores are stored as the distance from the position, not from the root - the same
+// position is reached at other plies and in searches from other roots.
 #[derive(Debug, Copy, Clone)]
 pub struct TEntry {
-    key: u64,
     depth: u16,
     score: i16,
-    data: u16, // frm, to, bound: 2x6 + 2 = 14 bits
-               // 2x16 + 14 = 46
-               // 64-46 = 18 unused with u64 alignment
-               // 18 + 23 redundant hash = 41
-               // TODO - store extra moves without score, e.g. 41 available / 12 per move => +3 moves (36 bits)
+    data: u16,      // best move - Move::data, promotions included
+    bound: u8,      // exact, lower or upper
+    generation: u8, // search the entry was stored in
 }
 
 impl Default for TEntry {
     #[inline(always)]
     fn default() -> TEntry {
         TEntry {
-            key: 0,
             depth: 0,
             score: 0,
             data: 0,
+            bound: 0,
+            generation: 0,
         }
     }
 }
 
 impl TEntry {
     // Score is either exact, a lower bound or an upper bound
-    const EXACT_BIT: u16 = 1 << 12;
-    const LOWER_BIT: u16 = 1 << 13;
+    const EXACT_BIT: u8 = 1;
+    const LOWER_BIT: u8 = 2;
     #[inline(always)]
     pub fn exact_bound(&self) -> bool {
-        self.data & TEntry::EXACT_BIT != 0
+        self.bound & TEntry::EXACT_BIT != 0
     }
     #[inline(always)]
     pub fn lower_bound(&self) -> bool {
-        self.data & TEntry::LOWER_BIT != 0
+        self.bound & TEntry::LOWER_BIT != 0
     }
 
     #[inline(always)]
-    pub fn frmto(&self) -> (u8, u8) {
-        (mgen::ext_frm(self.data), mgen::ext_to(self.data))
+    pub fn best_move(&self) -> Move {
+        Move {
+            data: self.data,
+            val: 0,
+        }
     }
 
     #[inline(always)]
@@ -66,55 +91,250 @@ impl TEntry {
         self.depth
     }
 
+    /// Score of the entry probed `ply` plies from the root
+    #[inline(always)]
+    pub fn score(&self, ply: usize) -> i16 {
+        match self.score {
+            s if s >= MATE => s - ply as i16,
+            s if s <= -MATE => s + ply as i16,
+            s => s,
+        }
+    }
+
     #[inline(always)]
-    pub fn score(&self) -> i16 {
-        self.score
+    fn pack(&self) -> u64 {
+        self.depth as u64
+            | (self.score as u16 as u64) << 16
+            | (self.data as u64) << 32
+            | (self.bound as u64) << 48
+            | (self.generation as u64) << 56
     }
+
+    #[inline(always)]
+    fn unpack(packed: u64) -> TEntry {
+        TEntry {
+            depth: packed as u16,
+            score: (packed >> 16) as u16 as i16,
+            data: (packed >> 32) as u16,
+            bound: (packed >> 48) as u8,
+            generation: (packed >> 56) as u8,
+        }
+    }
+}
+
+// An entry is stored as two atomic words - the key xor'ed with the packed data, and
+// the packed data. Threads may write the same slot simultaneously without locking;
+// a torn write then shows up as a key mismatch on probing (Hyatt & Mann's lockless hashing).
+#[derive(Default)]
+struct Slot {
+    key: AtomicU64,
+    data: AtomicU64,
 }
 
-pub struct Transpositions(Vec<TEntry>);
+/// Transposition table - lock free, can be shared between search threads.
+/// Entries are stamped with the search generation, so entries from earlier searches
+/// are kept until they are overwritten - no need to clear the table between moves.
+pub struct Transpositions {
+    slots: Vec<Slot>,
+    mask: usize, // number of buckets - 1
+    generation: AtomicU8,
+}
 
 impl Default for Transpositions {
     fn default() -> Transpositions {
-        Transpositions(vec![TEntry::default(); TABLE_SIZE])
+        Transpositions::with_capacity_mb(DEFAULT_HASH_MB)
     }
 }
 
-#[inline(always)]
-fn index(key: u64) -> usize {
-    key as usize & MASK
+impl Slot {
+    #[inline(always)]
+    fn load(&self) -> (u64, u64) {
+        let packed = self.data.load(Ordering::Relaxed);
+        (self.key.load(Ordering::Relaxed) ^ packed, packed)
+    }
 }
 
 impl Transpositions {
-    pub fn store(&mut self, key: u64, depth: u16, score: i16, alpha: i16, beta: i16, m: &Move) {
-        let bound = if score <= alpha {
-            0 // Upper bound
-        } else if score >= beta {
-            TEntry::LOWER_BIT
-        } else {
-            TEntry::EXACT_BIT
+    /// Number of entries a table of (at most) `mb` MB holds - a power of two
+    pub fn entries_for_mb(mb: usize) -> usize {
+        let n = (mb.max(1) << 20) / std::mem::size_of::<Slot>();
+        1 << n.ilog2()
+    }
+
+    /// Table using at most `mb` MB - the number of entries is rounded down to a power of 2
+    pub fn with_capacity_mb(mb: usize) -> Transpositions {
+        let n = Transpositions::entries_for_mb(mb);
+        Transpositions {
+            slots: (0..n).map(|_| Slot::default()).collect(),
+            mask: n / BUCKET_SIZE - 1,
+            generation: AtomicU8::new(0),
+        }
+    }
+
+    /// Size in MB
+    pub fn capacity_mb(&self) -> usize {
+        (self.slots.len() * std::mem::size_of::<Slot>()) >> 20
+    }
+
+    #[inline(always)]
+    fn index(&self, key: u64) -> usize {
+        (key as usize & self.mask) * BUCKET_SIZE
+    }
+
+    /// Start a new search - entries from earlier searches become candidates for replacement
+    pub fn new_search(&self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Store the score of a position searched `ply` plies from the root
+    pub fn store(&self, key: u64, depth: u16, ply: usize, score: i16, bound: Bound, m: &Move) {
+        let score = match score {
+            s if s >= MATE => s + ply as i16,
+            s if s <= -MATE => s - ply as i16,
+            s => s,
+        };
+        let bound = match bound {
+            Bound::Upper => 0,
+            Bound::Lower => TEntry::LOWER_BIT,
+            Bound::Exact => TEntry::EXACT_BIT,
         };
-        let data = (m.data & (mgen::FRM_MASK | mgen::TO_MASK)) | bound;
+        let generation = self.generation.load(Ordering::Relaxed);
         let e = TEntry {
-            key,
             depth,
             score,
-            data,
+            data: m.data,
+            bound,
+            generation,
         };
 
-        self.0[index(key)] = e
+        // depth-preferred slot unless it holds a deeper search of another position
+        // from this search - then the always-replace slot
+        let i = self.index(key);
+        let (k, packed) = self.slots[i].load();
+        let old = TEntry::unpack(packed);
+        let slot = if k == key || old.generation != generation || depth >= old.depth {
+            &self.slots[i]
+        } else {
+            &self.slots[i + 1]
+        };
+
+        let packed = e.pack();
+        slot.key.store(key ^ packed, Ordering::Relaxed);
+        slot.data.store(packed, Ordering::Relaxed);
     }
 
     pub fn len(&self) -> usize {
-        self.0.len()
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
     }
 
-    pub fn clear(&mut self) {
-        self.0.fill(TEntry::default());
+    pub fn clear(&self) {
+        for slot in &self.slots {
+            slot.key.store(0, Ordering::Relaxed);
+            slot.data.store(0, Ordering::Relaxed);
+        }
+    }
+
+    pub fn probe(&self, key: u64) -> Option<TEntry> {
+        let i = self.index(key);
+        self.slots[i..i + BUCKET_SIZE]
+            .iter()
+            .map(Slot::load)
+            .find(|&(k, _)| k == key)
+            .map(|(_, packed)| TEntry::unpack(packed))
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mgen::Board;
+
+    #[test]
+    fn test_store_probe() {
+        let mut board = Board::default();
+        let m = board.moves(false, false)[0];
+        let tt = Transpositions::default();
+        assert!(tt.probe(board.hash).is_none());
+
+        tt.store(board.hash, 7, 0, -123, Bound::Exact, &m);
+        let e = tt.probe(board.hash).unwrap();
+        assert_eq!(
+            (e.depth(), e.score(0), e.best_move().data),
+            (7, -123, m.data)
+        );
+        assert!(e.exact_bound() && !e.lower_bound());
+
+        // under-promotions survive the round trip
+        let board2 = Board::from_fen("8/1P2k3/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let m2 = board2
+            .legal_moves(false)
+            .into_iter()
+            .find(|m| m.transform() && m.promote_kind() == crate::val::KNIGHT)
+            .unwrap();
+        tt.store(board2.hash, 1, 0, 0, Bound::Exact, &m2);
+        assert_eq!(tt.probe(board2.hash).unwrap().best_move().data, m2.data);
+
+        board.update(&m);
+        assert!(tt.probe(board.hash).is_none());
+        tt.clear();
+        board.backdate(&m);
+        assert!(tt.probe(board.hash).is_none());
+    }
+
+    #[test]
+    fn test_mate_scores() {
+        let board = Board::default();
+        let m = board.moves(false, false)[0];
+        let tt = Transpositions::default();
+        // mated 3 plies below a node at ply 5 - mated in 3 from anywhere else
+        tt.store(board.hash, 4, 5, -INFINITE + 8, Bound::Lower, &m);
+        let e = tt.probe(board.hash).unwrap();
+        assert_eq!(e.score(5), -INFINITE + 8);
+        assert_eq!(e.score(1), -INFINITE + 4);
+        assert!(e.lower_bound() && !e.exact_bound());
+        tt.store(board.hash, 4, 2, INFINITE - 7, Bound::Upper, &m);
+        let e = tt.probe(board.hash).unwrap();
+        assert_eq!(e.score(6), INFINITE - 11);
+        assert!(!e.lower_bound() && !e.exact_bound());
+        assert_eq!(Bound::new(50, 50, 60), Bound::Upper);
+        assert_eq!(Bound::new(55, 50, 60), Bound::Exact);
+        assert_eq!(Bound::new(60, 50, 60), Bound::Lower);
+    }
+
+    #[test]
+    fn test_capacity() {
+        assert_eq!(Transpositions::with_capacity_mb(1).len(), 1 << 16);
+        assert_eq!(Transpositions::with_capacity_mb(3).capacity_mb(), 2);
+        assert_eq!(Transpositions::entries_for_mb(100), 1 << 22);
+        assert_eq!(Transpositions::default().capacity_mb(), DEFAULT_HASH_MB);
+    }
+
+    #[test]
+    fn test_replacement() {
+        let tt = Transpositions::with_capacity_mb(1);
+        let mut board = Board::default();
+        let m = board.moves(false, false)[0];
+        let key = board.hash;
+        tt.store(key, 8, 0, 10, Bound::Exact, &m);
+        // same bucket, other position, shallower - must not evict the deep entry
+        let key2 = key ^ (1 << 40);
+        let key3 = key ^ (1 << 41);
+        tt.store(key2, 2, 0, 20, Bound::Exact, &m);
+        tt.store(key3, 1, 0, 30, Bound::Exact, &m);
+        assert_eq!(tt.probe(key).unwrap().depth(), 8);
+        assert!(tt.probe(key2).is_none()); // replaced in the always-replace slot
+        assert_eq!(tt.probe(key3).unwrap().score(0), 30);
 
-    pub fn probe(&self, key: u64) -> Option<&TEntry> {
-        let entry = &self.0[index(key)];
-        if entry.key == key { Some(entry) } else { None }
+        // next search - the old deep entry can be replaced
+        tt.new_search();
+        tt.store(key2, 2, 0, 20, Bound::Exact, &m);
+        assert!(tt.probe(key).is_none());
+        assert_eq!(tt.probe(key2).unwrap().depth(), 2);
+        board.update(&m);
+        assert!(tt.probe(board.hash).is_none());
     }
 }
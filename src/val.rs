@@ -56,8 +56,8 @@ pub const HASH: [[[u64; 64]; 2]; 8] = [
     [Q1_HASH, Q2_HASH],
     [K1_HASH, K2_HASH],
     [P1_HASH, P2_HASH],
-    [NIL_HASH, NIL_HASH],
-    [NIL_HASH, NIL_HASH],
+    [[0; 64], [0; 64]], // empty squares do not contribute to the hash
+    [[0; 64], [0; 64]],
 ];
 
 const VAL: [[[i16; 64]; 2]; 8] = [